@@ -0,0 +1,96 @@
+use tracing::error;
+
+/// Command line arguments of a subcommand, split between positional values
+/// and `--flag` / `--option=value` switches.
+pub struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Args {
+        let mut positional = vec![];
+        let mut options = vec![];
+
+        for arg in args {
+            match arg.strip_prefix("--") {
+                Some(option) => match option.split_once('=') {
+                    Some((name, value)) => options.push((name.to_string(), Some(value.to_string()))),
+                    None => options.push((option.to_string(), None)),
+                },
+                None => positional.push(arg.to_string()),
+            }
+        }
+
+        Args { positional, options }
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(|arg| arg.as_str())
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    /// Last value given to `--name=value`, if any.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    /// Every value given to a repeatable `--name=value` option, in order.
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.options.iter()
+            .filter(|(option, _)| option == name)
+            .filter_map(|(_, value)| value.as_deref())
+            .collect()
+    }
+}
+
+/// Parses a size in bytes, with an optional `K`, `M`, `G` or `T` binary suffix.
+pub fn parse_size(size: &str) -> Result<u64, ()> {
+    let upper = size.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+
+    let unit: u64 = match upper[digits.len()..].trim_end_matches('B').trim_end_matches('I') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => {
+            error!("invalid size: {size}");
+            return Err(());
+        }
+    };
+
+    digits.trim().parse::<u64>().ok()
+        .and_then(|value| value.checked_mul(unit))
+        .ok_or_else(|| {
+            error!("invalid size: {size}");
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("1MB"), Ok(1 << 20));
+        assert_eq!(parse_size("10Gi"), Ok(10 << 30));
+        assert_eq!(parse_size("2KiB"), Ok(2 << 10));
+        assert_eq!(parse_size("3t"), Ok(3 << 40));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        assert_eq!(parse_size(""), Err(()));
+        assert_eq!(parse_size("G"), Err(()));
+        assert_eq!(parse_size("10X"), Err(()));
+        assert_eq!(parse_size("1.5G"), Err(()));
+        assert_eq!(parse_size("99999999999T"), Err(()));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use crate::args::Args;
+use crate::models::{Fragment, FileM, Manifest};
+use crate::source::source;
+use crate::{DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::error;
+
+pub async fn cat_from_args(args: &[String]) -> Result<(), ()> {
+    // -o <file> is accepted as well as --output=<file>
+    let mut rest = vec![];
+    let mut output = None;
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output = iter.next().map(PathBuf::from),
+            _ => rest.push(arg.to_string()),
+        }
+    }
+
+    let args = Args::parse(&rest);
+    let output = args.value("output").map(PathBuf::from).or(output);
+
+    let (game, version, path) = match (args.positional(0), args.positional(1), args.positional(2)) {
+        (Some(game), Some(version), Some(path)) => (game, version, path),
+        _ => {
+            error!("expected the game, the version and the path of the file");
+            return Err(());
+        }
+    };
+
+    let platform = args.positional(3).unwrap_or(DEFAULT_PLATFORM);
+    let release = args.positional(4).unwrap_or(DEFAULT_RELEASE);
+
+    let version = match version {
+        "0" => crate::get_latest_version(game, platform, release).await?,
+        version => version.to_string(),
+    };
+
+    let manifest = crate::get_manifest(game, &version, platform, release).await?;
+
+    let (fragment, file) = find_file(&manifest, path).ok_or_else(|| {
+        error!("{game} version {version} has no file {path}");
+    })?;
+
+    match output {
+        Some(output) => {
+            // written next to the output and renamed once verified
+            let part_path = crate::part_path(&output);
+            let mut writer = File::create(&part_path).map_err(|err| {
+                error!("could not create the file: {path} ({err})", path = part_path.display());
+            })?;
+
+            if cat(game, fragment, file, &mut writer).await.is_err() {
+                drop(writer);
+                let _ = fs::remove_file(&part_path);
+                return Err(());
+            }
+
+            crate::rename(&part_path, &output)
+        },
+        None => cat(game, fragment, file, &mut io::stdout().lock()).await,
+    }
+}
+
+/// The file at `path`, either `fragment/name` or a name looked up in every fragment.
+fn find_file<'a>(manifest: &'a Manifest, path: &str) -> Option<(&'a Fragment, &'a FileM)> {
+    let lookup = |fragment: &'a Fragment, name: &str| {
+        fragment.files.iter().find(|file| file.name == name).map(|file| (fragment, file))
+    };
+
+    manifest.fragments.iter()
+        .find_map(|fragment| path.strip_prefix(&format!("{}/", fragment.name)).and_then(|name| lookup(fragment, name)))
+        .or_else(|| manifest.fragments.iter().find_map(|fragment| lookup(fragment, path)))
+}
+
+/// Writes the file chunk by chunk, each one read from its bundle with a range
+/// request, or the whole file from the `hashes/` endpoint when a chunk is in
+/// no bundle. Every chunk is verified before being written.
+async fn cat(game: &str, fragment: &Fragment, file: &FileM, writer: &mut impl Write) -> Result<(), ()> {
+    // chunk hash -> (bundle hash, offset in the bundle)
+    let mut locations: HashMap<&str, (&str, u64)> = HashMap::new();
+    for bundle in &fragment.bundles {
+        for chunk in &bundle.chunks {
+            locations.insert(&chunk.hash, (&bundle.hash, chunk.offset));
+        }
+    }
+
+    let chunks = crate::get_file_chunks(file);
+    let mut hasher = sha1_smol::Sha1::new();
+
+    if !chunks.iter().all(|(hash, _)| locations.contains_key(hash)) {
+        let path = format!("{game}/hashes/{}/{}", &file.hash[..2], file.hash);
+        let bytes = source().get(&path).await?;
+        hasher.update(&bytes);
+        check_hash(file, &hasher)?;
+        return write(writer, &bytes);
+    }
+
+    for (hash, size) in chunks {
+        let (bundle, offset) = locations[hash];
+        let path = format!("{game}/bundles/{}/{}", &bundle[..2], bundle);
+        let bytes = source().get_range(&path, offset, size).await?;
+
+        if crate::sha1_bytes(&bytes) != hash {
+            error!("the chunk {} of the bundle {} is corrupted", hash, bundle);
+            return Err(());
+        }
+
+        hasher.update(&bytes);
+        write(writer, &bytes)?;
+    }
+
+    check_hash(file, &hasher)?;
+    writer.flush().map_err(|err| {
+        error!("could not write the file {}: {err}", file.name);
+    })
+}
+
+fn check_hash(file: &FileM, hasher: &sha1_smol::Sha1) -> Result<(), ()> {
+    let hash = hasher.digest().to_string();
+    if hash != file.hash {
+        error!("the file {} is corrupted ({}, expected {})", file.name, hash, file.hash);
+        return Err(());
+    }
+
+    Ok(())
+}
+
+fn write(writer: &mut impl Write, bytes: &[u8]) -> Result<(), ()> {
+    writer.write_all(bytes).map_err(|err| {
+        error!("could not write the output: {err}");
+    })
+}
@@ -0,0 +1,126 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{error, warn};
+
+/// Size the store is trimmed to when no limit is given.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Local content-addressed store of chunks, laid out as `ab/abcdef...`, shared
+/// by every version and every game so a chunk is only downloaded once.
+///
+/// The modification time of a chunk is refreshed each time it is read, which
+/// lets `gc` evict the least recently used chunks first.
+pub struct ChunkStore {
+    root: PathBuf,
+    max_size: u64,
+}
+
+impl ChunkStore {
+    pub fn new(root: PathBuf, max_size: u64) -> ChunkStore {
+        ChunkStore { root, max_size }
+    }
+
+    /// `$XDG_CACHE_HOME/cytrus/chunks`.
+    pub fn default_root() -> Option<PathBuf> {
+        Some(crate::cache_dir()?.join("chunks"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn chunk_path(&self, hash: &str) -> PathBuf {
+        self.root.join(&hash[..2]).join(hash)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.chunk_path(hash).is_file()
+    }
+
+    /// Content of the chunk, or `None` if it is missing or corrupted.
+    pub fn get(&self, hash: &str) -> Option<Vec<u8>> {
+        let path = self.chunk_path(hash);
+        let bytes = fs::read(&path).ok()?;
+
+        if crate::sha1_bytes(&bytes) != hash {
+            warn!("removing the corrupted chunk {path}", path = path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(bytes)
+    }
+
+    /// Stores the chunk, unless its content does not match its hash.
+    pub fn put(&self, hash: &str, bytes: &[u8]) -> Result<(), ()> {
+        let path = self.chunk_path(hash);
+
+        if path.is_file() {
+            return Ok(());
+        }
+
+        if crate::sha1_bytes(bytes) != hash {
+            warn!("not storing the chunk {hash}, its content does not match its hash");
+            return Ok(());
+        }
+
+        crate::create_dir_all(&self.root.join(&hash[..2]))?;
+        crate::write_atomic(&path, bytes)
+    }
+
+    /// Evicts the least recently used chunks until the store fits in its
+    /// maximum size, returns the number of chunks and bytes removed.
+    pub fn gc(&self) -> Result<(u64, u64), ()> {
+        let mut chunks = vec![];
+        let mut total = 0;
+
+        let prefixes = match fs::read_dir(&self.root) {
+            Ok(prefixes) => prefixes,
+            Err(_) => return Ok((0, 0)),
+        };
+
+        for prefix in prefixes.filter_map(|entry| entry.ok()) {
+            let entries = match fs::read_dir(prefix.path()) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let metadata = match entry.metadata() {
+                    Ok(metadata) if metadata.is_file() => metadata,
+                    _ => continue,
+                };
+
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                total += metadata.len();
+                chunks.push((used, metadata.len(), entry.path()));
+            }
+        }
+
+        chunks.sort();
+
+        let mut removed = 0;
+        let mut freed = 0;
+
+        for (_, size, path) in chunks {
+            if total <= self.max_size {
+                break;
+            }
+
+            fs::remove_file(&path).map_err(|err| {
+                error!("could not remove the file: {path} ({err})", path = path.display());
+            })?;
+
+            total -= size;
+            removed += 1;
+            freed += size;
+        }
+
+        Ok((removed, freed))
+    }
+}
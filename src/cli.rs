@@ -0,0 +1,437 @@
+//! The command line of the `cytrus-downloader-v6` binary.
+
+use std::{env, fs};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use serde::Serialize;
+use crate::args::{self, Args};
+use crate::chunk_store::{self, ChunkStore};
+use crate::events::{self, Observer};
+use crate::receipt::Receipt;
+use crate::source::{self, Source};
+use crate::{cat, diff, find, history, logging, mirror, pack, serve, show, watch};
+use crate::{check_version_exists, find_version, get_all_targets, get_cytrus_root, get_latest_version, install_root, parse_target};
+use crate::{DownloadMode, DownloadOptions, Target};
+use crate::{CYTRUS_BASE_URL, DEFAULT_DIR_OUT, DEFAULT_GAME, DEFAULT_JOBS, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+const EXIT_UPDATE_AVAILABLE: u8 = 10;
+
+pub async fn entry() -> Result<ExitCode, ()> {
+    let mut args: Vec<String> = env::args().collect();
+
+    // -v, -vv and -q are accepted anywhere, before or after the subcommand
+    let verbosity = args.iter().map(|arg| match arg.as_str() {
+        "-v" => 1,
+        "-vv" => 2,
+        "-q" => -1,
+        _ => 0,
+    }).sum::<i8>();
+    args.retain(|arg| !matches!(arg.as_str(), "-v" | "-vv" | "-q"));
+
+    let json = Args::parse(&args[1..]).value("log-format") == Some("json");
+    logging::init(verbosity, json);
+
+    let program = &args[0];
+
+    if args.len() < 2 {
+        usage(program);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let sub_command = &args[1];
+
+    if let Some(base_url) = Args::parse(&args[2..]).value("base-url") {
+        source::set_source(Source::parse(base_url));
+    }
+
+    match sub_command.as_str() {
+        "download" => download_from_args(&args).await?,
+        "status" => status_from_args(&args).await?,
+        "check-update" => return check_update_from_args(&args).await,
+        "watch" => watch::watch_from_args(&args).await?,
+        "cache" => cache_from_args(&args).await?,
+        "diff" => diff::diff_from_args(&args).await?,
+        "manifest" => show::manifest_from_args(&args).await?,
+        "fragments" => show::fragments_from_args(&args).await?,
+        "cat" => cat::cat_from_args(&args).await?,
+        "find" => find::find_from_args(&args).await?,
+        "history" => history::history_from_args(&args).await?,
+        "pack" => pack::pack_from_args(&args)?,
+        "serve" => serve::serve_from_args(&args).await?,
+        "mirror" => mirror::mirror_from_args(&args).await?,
+        _ => {
+            error!("unknown subcommand: {}", sub_command);
+            usage(program);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn usage(program: &str) {
+    eprintln!("Usage: {program} [SUBCOMMAND] [OPTIONS]");
+    eprintln!("Options:");
+    eprintln!("    -v, -vv                                             log every bundle, file and chunk handled");
+    eprintln!("    -q                                                  only log the errors");
+    eprintln!("                                                        RUST_LOG overrides both, e.g. RUST_LOG=debug");
+    eprintln!("    --log-format=<format>                               text or json, one object per line, text by default");
+    eprintln!("    --base-url=<url>                                    read cytrus from this http(s) url, file:// url or");
+    eprintln!("                                                        directory instead of {CYTRUS_BASE_URL}");
+    eprintln!("Subcommands:");
+    eprintln!("    download [game] [version] [platform] [release]      download the <game> with");
+    eprintln!("                                                        the <version> or latest if not specified");
+    eprintln!("                                                        on the <platform> or windows if not specified [windows|linux|darwin]");
+    eprintln!("                                                        on the <release> or main if not specified [main|beta]");
+    eprintln!("        --rehash                                        hash every installed file again instead of");
+    eprintln!("                                                        trusting the cached hashes");
+    eprintln!("        --all                                           download the latest version of every game, platform");
+    eprintln!("                                                        and release listed by cytrus");
+    eprintln!("        --config=<file>                                 download the latest version of every game/platform/release");
+    eprintln!("                                                        listed in the <file>, one per line");
+    eprintln!("        --jobs=<n>                                      number of bundles downloaded at once, 8 by default");
+    eprintln!("        --mode=<mode>                                   bundles to extract the files from whole bundles, files");
+    eprintln!("                                                        to fetch each file on its own, auto to fetch files on");
+    eprintln!("                                                        their own when few of their bundles is needed,");
+    eprintln!("                                                        bundles by default");
+    eprintln!("        --chunk-store[=<dir>]                           reuse the chunks already downloaded from the local");
+    eprintln!("                                                        store, ~/.cache/cytrus/chunks by default");
+    eprintln!("        --chunk-store-max=<size>                        size the store is trimmed to, 10G by default");
+    eprintln!("        --events=json                                   write the progress as json events, one per line");
+    eprintln!("        --events-fd=<fd>                                write the events to this inherited file descriptor");
+    eprintln!("                                                        instead of stdout");
+    eprintln!("        --dry-run                                       only print what would be downloaded and written");
+    eprintln!("        --fragment=<name>                               only install this fragment, can be repeated");
+    eprintln!("        --exclude-fragment=<name>                       do not install this fragment, can be repeated");
+    eprintln!("        --include=<glob>                                only install the files matching the <glob>, can be");
+    eprintln!("                                                        repeated, a <glob> without / matches the file name");
+    eprintln!("        --exclude=<glob>                                do not install the files matching the <glob>, can be");
+    eprintln!("                                                        repeated");
+    eprintln!("    status [game] [platform] [release]                  show the version installed for the <game>");
+    eprintln!("                                                        on the <platform> and whether it is the latest");
+    eprintln!("    check-update [game] [platform] [release]            compare the installed version of the <game> with");
+    eprintln!("                                                        the latest one, or of every install if not specified");
+    eprintln!("                                                        exits with 0 if up to date, 10 if an update is available");
+    eprintln!("        --json                                          print the result as json");
+    eprintln!("    watch [game/platform/release...]                    poll cytrus and install every new version of the");
+    eprintln!("                                                        targets, or of dofus/windows/main if not specified");
+    eprintln!("        --interval=<seconds>                            time between two polls, 300 by default");
+    eprintln!("        --hook=<command>                                shell command run after each update, with");
+    eprintln!("                                                        CYTRUS_GAME, CYTRUS_PLATFORM, CYTRUS_RELEASE,");
+    eprintln!("                                                        CYTRUS_OLD_VERSION and CYTRUS_NEW_VERSION set");
+    eprintln!("    cache gc                                            evict the least recently used chunks of the store");
+    eprintln!("        --chunk-store=<dir>                             store to trim, ~/.cache/cytrus/chunks by default");
+    eprintln!("        --chunk-store-max=<size>                        size the store is trimmed to, 10G by default");
+    eprintln!("    diff <from> <to> [game] [platform] [release]        list the files added, removed and modified between");
+    eprintln!("                                                        the versions <from> and <to>, and what an update downloads");
+    eprintln!("        --json                                          print the diff as json");
+    eprintln!("    manifest show [game] [version] [platform] [release] print the manifest of the <version>, or latest");
+    eprintln!("                                                        if not specified");
+    eprintln!("        --file=<path>                                   read the manifest from a local file instead");
+    eprintln!("        --format=<format>                               json, yaml or csv, json by default");
+    eprintln!("        --fragment=<name>                               only show this fragment, can be repeated");
+    eprintln!("        --path=<glob>                                   only show the files matching the <glob>, a <glob>");
+    eprintln!("                                                        without / matches the file name");
+    eprintln!("    fragments [game] [version] [platform] [release]     list the fragments of the <version>, or latest if not");
+    eprintln!("                                                        specified, with their number of files and size");
+    eprintln!("    cat <game> <version> <path> [platform] [release]   write the file at <path>, as fragment/name or name, of");
+    eprintln!("                                                        the <version> to stdout, fetching only its chunks");
+    eprintln!("        -o <file>, --output=<file>                      write it to the <file> instead");
+    eprintln!("    find <glob|sha1> [version...]                       list the files matching the <glob>, or with the <sha1>,");
+    eprintln!("                                                        in each <version>, or in the latest and every cached one");
+    eprintln!("        --game=<game>                                   dofus by default");
+    eprintln!("        --platform=<platform>                           windows by default");
+    eprintln!("        --release=<release>                             main by default");
+    eprintln!("        --json                                          print the matches as json");
+    eprintln!("    history [game] [platform] [release]                 list every version seen by the previous runs or whose");
+    eprintln!("                                                        manifest is cached, which can still be downloaded");
+    eprintln!("        --json                                          print the history as json");
+    eprintln!("    pack <dir> <out> <game> <version> [platform] [release]");
+    eprintln!("                                                        build the manifest and bundles of the <version> from");
+    eprintln!("                                                        <dir>, whose sub directories are the fragments, into");
+    eprintln!("                                                        the cytrus layout in <out>");
+    eprintln!("        --fragment=<name>:<glob>                        put the files matching the <glob> in the fragment <name>");
+    eprintln!("                                                        instead, can be repeated, the others go in main");
+    eprintln!("        --chunk-size=<size>                             maximum size of a chunk, 1M by default");
+    eprintln!("        --bundle-size=<size>                            maximum size of a bundle, 16M by default");
+    eprintln!("        --hashes                                        also write every file under hashes/");
+    eprintln!("    serve [dir]                                         serve the <dir>, or ./mirror if not specified, over http");
+    eprintln!("                                                        with the cytrus url scheme");
+    eprintln!("        --listen=<addr>                                 address to listen on, 127.0.0.1:8080 by default");
+    eprintln!("    mirror [game/platform/release...]                   copy cytrus.json, the manifest, the raw bundles and the files");
+    eprintln!("                                                        under hashes/ of the latest version of the targets, or of");
+    eprintln!("                                                        dofus/windows/main if not specified, keeping the cdn layout");
+    eprintln!("        --dir=<dir>                                     where to mirror, ./mirror by default");
+    eprintln!("        --all                                           mirror every game, platform and release listed by cytrus");
+    eprintln!("        --jobs=<n>                                      number of bundles downloaded at once, 8 by default");
+}
+
+async fn download_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let rehash = args.flag("rehash");
+
+    let jobs = jobs_from_args(&args)?;
+
+    let chunk_store = if args.flag("chunk-store") {
+        Some(chunk_store_from_args(&args)?)
+    } else {
+        None
+    };
+
+    let mode = DownloadMode::parse(args.value("mode").unwrap_or("bundles"))?;
+
+    let dry_run = args.flag("dry-run");
+
+    let fragments = args.values("fragment").iter().map(|name| name.to_string()).collect();
+    let exclude_fragments = args.values("exclude-fragment").iter().map(|name| name.to_string()).collect();
+
+    let include = patterns_from_args(&args, "include")?;
+    let exclude = patterns_from_args(&args, "exclude")?;
+
+    let observer = events::events_from_args(&args)?.map(|sink| Arc::new(sink) as Arc<dyn Observer>);
+
+    let cancel = cancel_on_ctrl_c();
+
+    let options = DownloadOptions {
+        rehash, jobs, mode, chunk_store, dry_run, fragments, exclude_fragments, include, exclude, observer, cancel,
+        out: PathBuf::from(DEFAULT_DIR_OUT),
+        source: source::source().clone(),
+        progress_bars: true,
+    };
+
+    let targets = if args.flag("all") || args.value("config").is_some() {
+        get_batch_targets(&args).await?
+    } else {
+        let game = args.positional(0).unwrap_or(DEFAULT_GAME);
+        let mut version = args.positional(1).unwrap_or("0").to_string();
+        let platform = args.positional(2).unwrap_or(DEFAULT_PLATFORM);
+        let release = args.positional(3).unwrap_or(DEFAULT_RELEASE);
+
+        if version == "0" {
+            let body = get_cytrus_root().await?;
+            history::record(&body);
+            version = find_version(&body, game, platform, release)?;
+        } else {
+            check_version_exists(game, &version, platform, release).await?;
+        }
+
+        vec![Target::new(game, &version, platform, release)]
+    };
+
+    crate::download_targets(&targets, &options).await.map_err(|err| {
+        if !options.cancel.is_cancelled() {
+            error!("could not download the game: {:?}", err);
+        }
+    })
+}
+
+/// A token cancelled by the first Ctrl-C, to stop the downloads cleanly,
+/// the second one exits right away.
+pub(crate) fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("interrupted, stopping once the writes in progress are done, press Ctrl-C again to exit now");
+            cancel.cancel();
+        }
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+
+    token
+}
+
+pub(crate) fn jobs_from_args(args: &Args) -> Result<usize, ()> {
+    match args.value("jobs") {
+        Some(jobs) => jobs.parse::<usize>().ok().filter(|jobs| *jobs > 0).ok_or_else(|| {
+            error!("invalid number of jobs: {jobs}");
+        }),
+        None => Ok(DEFAULT_JOBS),
+    }
+}
+
+/// The store given by `--chunk-store[=<dir>]`, trimmed to `--chunk-store-max=<size>`.
+fn chunk_store_from_args(args: &Args) -> Result<ChunkStore, ()> {
+    let root = match args.value("chunk-store") {
+        Some(root) => PathBuf::from(root),
+        None => ChunkStore::default_root().ok_or_else(|| {
+            error!("could not find the cache directory, use --chunk-store=<dir>");
+        })?,
+    };
+
+    let max_size = match args.value("chunk-store-max") {
+        Some(size) => args::parse_size(size)?,
+        None => chunk_store::DEFAULT_MAX_SIZE,
+    };
+
+    Ok(ChunkStore::new(root, max_size))
+}
+
+async fn cache_from_args(args: &[String]) -> Result<(), ()> {
+    let action = args.get(2).map(|action| action.as_str());
+    let args = Args::parse(&args[2..]);
+
+    match action {
+        Some("gc") => {
+            let store = chunk_store_from_args(&args)?;
+            let (removed, freed) = store.gc()?;
+            println!("Removed {} chunks ({} bytes) from {}", removed, freed, store.root().display());
+            Ok(())
+        },
+        _ => {
+            error!("unknown cache action, expected: cache gc");
+            Err(())
+        }
+    }
+}
+
+fn patterns_from_args(args: &Args, name: &str) -> Result<Vec<glob::Pattern>, ()> {
+    args.values(name).iter().map(|pattern| glob::Pattern::new(pattern).map_err(|err| {
+        error!("invalid path pattern {pattern}: {err}");
+    })).collect()
+}
+
+/// Targets of `download --all` or `download --config=<file>`, all at their latest version.
+async fn get_batch_targets(args: &Args) -> Result<Vec<Target>, ()> {
+    let body = get_cytrus_root().await?;
+    history::record(&body);
+
+    let mut targets = vec![];
+
+    if args.flag("all") {
+        targets.extend(get_all_targets(&body));
+    }
+
+    if let Some(config) = args.value("config") {
+        let content = fs::read_to_string(config).map_err(|err| {
+            error!("could not read the file: {config} ({err})");
+        })?;
+
+        // one game/platform/release per line, # starts a comment
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (game, platform, release) = parse_target(line)?;
+            let version = find_version(&body, &game, &platform, &release)?;
+            targets.push(Target::new(&game, &version, &platform, &release));
+        }
+    }
+
+    // a target given twice would be written twice at once in the same root
+    let mut roots = HashSet::new();
+    targets.retain(|target| roots.insert(target.root(Path::new(DEFAULT_DIR_OUT))));
+
+    Ok(targets)
+}
+
+async fn status_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let game = args.positional(0).unwrap_or(DEFAULT_GAME);
+    let platform = args.positional(1).unwrap_or(DEFAULT_PLATFORM);
+    let release = args.positional(2).unwrap_or(DEFAULT_RELEASE);
+
+    let out_path = &install_root(Path::new(DEFAULT_DIR_OUT), game, platform, release);
+
+    let receipt = match Receipt::load(out_path)? {
+        Some(receipt) => receipt,
+        None => {
+            println!("{} is not installed in {}", game, out_path.display());
+            return Ok(());
+        }
+    };
+
+    println!("Game:          {}", receipt.game);
+    println!("Platform:      {}", receipt.platform);
+    println!("Release:       {}", receipt.release);
+    println!("Version:       {}", receipt.version);
+    println!("Manifest hash: {}", receipt.manifest_hash);
+    println!("Installed at:  {} (unix time)", receipt.timestamp);
+    println!("Files:         {}", receipt.files.len());
+
+    let latest = get_latest_version(&receipt.game, &receipt.platform, &receipt.release).await?;
+
+    if latest == receipt.version {
+        println!("Latest:        {} (up to date)", latest);
+    } else {
+        println!("Latest:        {} (update available)", latest);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct UpdateCheck {
+    game: String,
+    platform: String,
+    release: String,
+    current: Option<String>,
+    latest: String,
+    update_available: bool,
+}
+
+async fn check_update_from_args(args: &[String]) -> Result<ExitCode, ()> {
+    let args = Args::parse(&args[2..]);
+    let out_path = Path::new(DEFAULT_DIR_OUT);
+
+    // (game, platform, release, installed version)
+    let mut targets = vec![];
+
+    match args.positional(0) {
+        Some(game) => {
+            let platform = args.positional(1).unwrap_or(DEFAULT_PLATFORM);
+            let release = args.positional(2).unwrap_or(DEFAULT_RELEASE).to_string();
+            let receipt = Receipt::load(&install_root(out_path, game, platform, &release))?;
+            let current = receipt.filter(|receipt| receipt.release == release).map(|receipt| receipt.version);
+            targets.push((game.to_string(), platform.to_string(), release, current));
+        },
+        None => {
+            for receipt in Receipt::find_all(out_path)? {
+                targets.push((receipt.game, receipt.platform, receipt.release, Some(receipt.version)));
+            }
+        }
+    }
+
+    let body = get_cytrus_root().await?;
+    history::record(&body);
+
+    let mut checks = vec![];
+
+    for (game, platform, release, current) in targets {
+        let latest = find_version(&body, &game, &platform, &release)?;
+        let update_available = current.as_deref() != Some(latest.as_str());
+        checks.push(UpdateCheck { game, platform, release, current, latest, update_available });
+    }
+
+    if args.flag("json") {
+        let json = serde_json::to_string_pretty(&checks).map_err(|err| {
+            error!("could not serialize the result: {err}");
+        })?;
+        println!("{json}");
+    } else {
+        if checks.is_empty() {
+            println!("Nothing is installed in {}", out_path.display());
+        }
+
+        for check in &checks {
+            println!("{} {} {}: {} -> {}{}", check.game, check.platform, check.release,
+                     check.current.as_deref().unwrap_or("not installed"), check.latest,
+                     if check.update_available { " (update available)" } else { " (up to date)" });
+        }
+    }
+
+    if checks.iter().any(|check| check.update_available) {
+        Ok(ExitCode::from(EXIT_UPDATE_AVAILABLE))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashSet};
+use serde::Serialize;
+use crate::args::Args;
+use crate::models::{FileM, Fragment, Manifest};
+use crate::{DEFAULT_GAME, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::error;
+
+#[derive(Serialize)]
+pub struct ManifestDiff {
+    pub from: String,
+    pub to: String,
+    pub fragments: Vec<FragmentDiff>,
+    /// Chunks of the new version missing from the old one, the bytes that really changed.
+    pub new_chunks: u64,
+    pub new_bytes: u64,
+    /// Bundles holding at least one new chunk, which an update downloads whole
+    /// in the default bundles mode.
+    pub new_bundles: u64,
+    pub bundle_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct FragmentDiff {
+    pub name: String,
+    pub added: Vec<FileChange>,
+    pub removed: Vec<FileChange>,
+    pub modified: Vec<FileChange>,
+    pub size_delta: i64,
+    pub new_chunks: u64,
+    pub new_bytes: u64,
+    pub new_bundles: u64,
+    pub bundle_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+    pub hash_before: Option<String>,
+    pub hash_after: Option<String>,
+    pub executable_changed: bool,
+    pub symlink_changed: bool,
+}
+
+pub async fn diff_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+
+    let (from, to) = match (args.positional(0), args.positional(1)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            error!("expected two versions to compare");
+            return Err(());
+        }
+    };
+
+    let game = args.positional(2).unwrap_or(DEFAULT_GAME);
+    let platform = args.positional(3).unwrap_or(DEFAULT_PLATFORM);
+    let release = args.positional(4).unwrap_or(DEFAULT_RELEASE);
+
+    let manifest_from = crate::get_manifest(game, from, platform, release).await?;
+    let manifest_to = crate::get_manifest(game, to, platform, release).await?;
+
+    let diff = diff(from, &manifest_from, to, &manifest_to);
+
+    if args.flag("json") {
+        let json = serde_json::to_string_pretty(&diff).map_err(|err| {
+            error!("could not serialize the diff: {err}");
+        })?;
+        println!("{json}");
+    } else {
+        print_diff(&diff);
+    }
+
+    Ok(())
+}
+
+pub fn diff(from: &str, manifest_from: &Manifest, to: &str, manifest_to: &Manifest) -> ManifestDiff {
+    let mut names = manifest_from.fragments.iter()
+        .chain(manifest_to.fragments.iter())
+        .map(|fragment| fragment.name.as_str())
+        .collect::<Vec<&str>>();
+    names.sort();
+    names.dedup();
+
+    let known_chunks = manifest_from.fragments.iter()
+        .flat_map(|fragment| fragment.files.iter())
+        .flat_map(crate::get_file_chunks)
+        .map(|(hash, _)| hash)
+        .collect::<HashSet<&str>>();
+
+    // a chunk or a bundle shared by several files or fragments is only downloaded once
+    let mut seen_chunks = HashSet::new();
+    let mut seen_bundles = HashSet::new();
+    let mut fragments = vec![];
+
+    for name in names {
+        let mut fragment = diff_fragment(name, find_fragment(manifest_from, name), find_fragment(manifest_to, name));
+
+        if let Some(fragment_to) = find_fragment(manifest_to, name) {
+            let mut new_chunks = HashSet::new();
+
+            for (hash, size) in fragment_to.files.iter().flat_map(crate::get_file_chunks) {
+                if known_chunks.contains(hash) {
+                    continue;
+                }
+
+                new_chunks.insert(hash);
+                if seen_chunks.insert(hash) {
+                    fragment.new_chunks += 1;
+                    fragment.new_bytes += size;
+                }
+            }
+
+            for bundle in &fragment_to.bundles {
+                if bundle.chunks.iter().any(|chunk| new_chunks.contains(chunk.hash.as_str())) && seen_bundles.insert(bundle.hash.as_str()) {
+                    fragment.new_bundles += 1;
+                    fragment.bundle_bytes += crate::plan::bundle_size(bundle);
+                }
+            }
+        }
+
+        fragments.push(fragment);
+    }
+
+    ManifestDiff {
+        from: from.to_string(),
+        to: to.to_string(),
+        new_chunks: fragments.iter().map(|fragment| fragment.new_chunks).sum(),
+        new_bytes: fragments.iter().map(|fragment| fragment.new_bytes).sum(),
+        new_bundles: fragments.iter().map(|fragment| fragment.new_bundles).sum(),
+        bundle_bytes: fragments.iter().map(|fragment| fragment.bundle_bytes).sum(),
+        fragments,
+    }
+}
+
+fn diff_fragment(name: &str, from: Option<&Fragment>, to: Option<&Fragment>) -> FragmentDiff {
+    let files_from = files_by_path(from);
+    let files_to = files_by_path(to);
+
+    let mut diff = FragmentDiff {
+        name: name.to_string(),
+        added: vec![],
+        removed: vec![],
+        modified: vec![],
+        size_delta: 0,
+        new_chunks: 0,
+        new_bytes: 0,
+        new_bundles: 0,
+        bundle_bytes: 0,
+    };
+
+    for (path, file) in &files_from {
+        if !files_to.contains_key(path) {
+            diff.removed.push(change(path, Some(file), None));
+            diff.size_delta -= file.size as i64;
+        }
+    }
+
+    for (path, file) in &files_to {
+        match files_from.get(path) {
+            None => {
+                diff.added.push(change(path, None, Some(file)));
+                diff.size_delta += file.size as i64;
+            },
+            Some(old) => {
+                let change = change(path, Some(old), Some(file));
+                if old.hash != file.hash || change.executable_changed || change.symlink_changed {
+                    diff.size_delta += file.size as i64 - old.size as i64;
+                    diff.modified.push(change);
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+fn find_fragment<'a>(manifest: &'a Manifest, name: &str) -> Option<&'a Fragment> {
+    manifest.fragments.iter().find(|fragment| fragment.name == name)
+}
+
+fn files_by_path(fragment: Option<&Fragment>) -> BTreeMap<&str, &FileM> {
+    fragment
+        .map(|fragment| fragment.files.iter().map(|file| (file.name.as_str(), file)).collect())
+        .unwrap_or_default()
+}
+
+fn change(path: &str, from: Option<&FileM>, to: Option<&FileM>) -> FileChange {
+    let both = from.zip(to);
+
+    FileChange {
+        path: path.to_string(),
+        size_before: from.map(|file| file.size),
+        size_after: to.map(|file| file.size),
+        hash_before: from.map(|file| file.hash.clone()),
+        hash_after: to.map(|file| file.hash.clone()),
+        executable_changed: both.is_some_and(|(from, to)| from.executable != to.executable),
+        symlink_changed: both.is_some_and(|(from, to)| from.symlink != to.symlink),
+    }
+}
+
+fn print_diff(diff: &ManifestDiff) {
+    println!("Diff {} -> {}", diff.from, diff.to);
+
+    for fragment in &diff.fragments {
+        println!("Fragment {}: {} added, {} removed, {} modified ({:+} bytes)",
+                 fragment.name, fragment.added.len(), fragment.removed.len(), fragment.modified.len(), fragment.size_delta);
+
+        for file in &fragment.added {
+            println!("  + {} ({} bytes)", file.path, file.size_after.unwrap_or(0));
+        }
+
+        for file in &fragment.removed {
+            println!("  - {} ({} bytes)", file.path, file.size_before.unwrap_or(0));
+        }
+
+        for file in &fragment.modified {
+            let mut flags = String::new();
+            if file.executable_changed {
+                flags.push_str(" [executable]");
+            }
+            if file.symlink_changed {
+                flags.push_str(" [symlink]");
+            }
+
+            println!("  ~ {} ({} -> {} bytes){}", file.path,
+                     file.size_before.unwrap_or(0), file.size_after.unwrap_or(0), flags);
+        }
+    }
+
+    println!("Update: {} new chunks ({} bytes), in {} bundles ({} bytes) to download", diff.new_chunks, diff.new_bytes, diff.new_bundles, diff.bundle_bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Bundle, Chunk, FileM, Fragment, Manifest};
+    use super::diff;
+
+    fn file(name: &str, hash: &str, chunks: &[(&str, u64)]) -> FileM {
+        let mut offset = 0;
+        let chunks = chunks.iter().map(|(hash, size)| {
+            offset += size;
+            Chunk { size: *size, hash: hash.to_string(), offset: offset - size }
+        }).collect::<Vec<Chunk>>();
+
+        FileM {
+            name: name.to_string(),
+            size: offset,
+            hash: hash.to_string(),
+            chunks,
+            executable: false,
+            symlink: String::new(),
+        }
+    }
+
+    fn bundle(hash: &str, chunks: &[(&str, u64)]) -> Bundle {
+        Bundle { hash: hash.to_string(), chunks: file(hash, hash, chunks).chunks }
+    }
+
+    fn fragment(name: &str, files: Vec<FileM>, bundles: Vec<Bundle>) -> Fragment {
+        Fragment { name: name.to_string(), files, bundles }
+    }
+
+    fn paths(changes: &[super::FileChange]) -> Vec<&str> {
+        changes.iter().map(|change| change.path.as_str()).collect()
+    }
+
+    #[test]
+    fn diffs_the_files_and_counts_what_an_update_downloads() {
+        let from = Manifest {
+            fragments: vec![
+                fragment("main", vec![
+                    file("a", "a1", &[("c1", 10), ("c2", 20)]),
+                    file("b", "b1", &[("c3", 5)]),
+                    file("r", "r1", &[("c4", 7)]),
+                ], vec![bundle("B1", &[("c1", 10), ("c2", 20), ("c3", 5), ("c4", 7)])]),
+            ],
+        };
+
+        let mut b = file("b", "b1", &[("c3", 5)]);
+        b.executable = true;
+
+        let to = Manifest {
+            fragments: vec![
+                fragment("lang", vec![
+                    file("l", "l1", &[("c5", 30)]),
+                ], vec![bundle("B2", &[("c1", 10), ("c5", 30)])]),
+                fragment("main", vec![
+                    file("a", "a2", &[("c1", 10), ("c5", 30)]),
+                    b,
+                    file("n", "n1", &[("c6", 4)]),
+                ], vec![bundle("B2", &[("c1", 10), ("c5", 30)]), bundle("B3", &[("c3", 5), ("c6", 4)])]),
+            ],
+        };
+
+        let diff = diff("1.0", &from, "1.1", &to);
+
+        let lang = &diff.fragments[0];
+        assert_eq!(lang.name, "lang");
+        assert_eq!(paths(&lang.added), ["l"]);
+        assert_eq!((lang.size_delta, lang.new_chunks, lang.new_bytes), (30, 1, 30));
+        assert_eq!((lang.new_bundles, lang.bundle_bytes), (1, 40));
+
+        let main = &diff.fragments[1];
+        assert_eq!(main.name, "main");
+        assert_eq!(paths(&main.added), ["n"]);
+        assert_eq!(paths(&main.removed), ["r"]);
+        assert_eq!(paths(&main.modified), ["a", "b"]);
+        assert!(!main.modified[0].executable_changed);
+        assert!(main.modified[1].executable_changed);
+        assert_eq!(main.size_delta, 4 - 7 + 10);
+
+        // c5 and B2 were already counted in lang
+        assert_eq!((main.new_chunks, main.new_bytes), (1, 4));
+        assert_eq!((main.new_bundles, main.bundle_bytes), (1, 9));
+
+        assert_eq!((diff.new_chunks, diff.new_bytes), (2, 34));
+        assert_eq!((diff.new_bundles, diff.bundle_bytes), (2, 49));
+    }
+
+    #[test]
+    fn finds_nothing_to_download_between_identical_versions() {
+        let manifest = Manifest {
+            fragments: vec![
+                fragment("main", vec![file("a", "a1", &[("c1", 10)])], vec![bundle("B1", &[("c1", 10)])]),
+            ],
+        };
+
+        let diff = diff("1.0", &manifest, "1.0", &manifest);
+
+        assert!(diff.fragments.iter().all(|fragment| fragment.added.is_empty() && fragment.removed.is_empty() && fragment.modified.is_empty()));
+        assert_eq!((diff.new_chunks, diff.new_bytes, diff.new_bundles, diff.bundle_bytes), (0, 0, 0, 0));
+    }
+}
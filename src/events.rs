@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+use crate::args::Args;
+
+/// Progress of a download for the programs wrapping this one, written by
+/// `--events=json` as one json object per line, tagged by `event`.
+/// Events and fields are only ever added, never renamed or removed.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    ManifestLoaded {
+        game: String,
+        version: String,
+        platform: String,
+        release: String,
+        manifest_hash: String,
+        fragments: usize,
+        files: usize,
+        /// Size of the files to update.
+        bytes: u64,
+    },
+    BundleStarted {
+        game: String,
+        bundle: String,
+        size: u64,
+    },
+    /// Sent at least every MiB while the bundle is downloaded.
+    BundleProgress {
+        game: String,
+        bundle: String,
+        bytes: u64,
+        size: u64,
+    },
+    FileCompleted {
+        game: String,
+        version: String,
+        fragment: String,
+        path: String,
+        size: u64,
+        hash: String,
+    },
+    VerifyFailed {
+        game: String,
+        /// `bundle` or `file`.
+        kind: String,
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    /// A manifest could not be loaded, or a bundle or a file could not be installed.
+    Error {
+        game: String,
+        /// `manifest`, `bundle` or `file`.
+        kind: String,
+        /// Version of the manifest, hash of the bundle or path of the file.
+        name: String,
+        /// The step that failed, the details are logged.
+        message: String,
+    },
+    Done {
+        success: bool,
+        /// Stopped by Ctrl-C or by the cancellation token of the download.
+        cancelled: bool,
+    },
+}
+
+/// Receives the events of a download, e.g. to show its progress in another UI.
+/// Called from the download tasks, so it should not block.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl Observer for EventSink {
+    fn on_event(&self, event: &Event) {
+        self.emit(event);
+    }
+}
+
+impl Observer for mpsc::UnboundedSender<Event> {
+    fn on_event(&self, event: &Event) {
+        // the receiver is gone, nobody is listening anymore
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Drops the events while the channel is full, rather than slowing down the download.
+impl Observer for mpsc::Sender<Event> {
+    fn on_event(&self, event: &Event) {
+        let _ = self.try_send(event.clone());
+    }
+}
+
+/// Where the events are written, stdout or an inherited file descriptor.
+pub struct EventSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> EventSink {
+        EventSink { writer: Mutex::new(writer) }
+    }
+
+    pub fn emit(&self, event: &Event) {
+        let mut line = match serde_json::to_vec(event) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("could not serialize the event: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            warn!("could not write the event: {err}");
+        }
+    }
+}
+
+/// The sink of `--events=json`, on stdout or on `--events-fd=<fd>`.
+pub fn events_from_args(args: &Args) -> Result<Option<EventSink>, ()> {
+    match args.value("events") {
+        // `--events json` would take json for the game
+        None if args.flag("events") => {
+            error!("--events expects a format, use --events=json");
+            return Err(());
+        },
+        None => return Ok(None),
+        Some("json") => {},
+        Some(format) => {
+            error!("unknown events format {format}, expected json");
+            return Err(());
+        }
+    }
+
+    let writer: Box<dyn Write + Send> = match args.value("events-fd") {
+        None if args.flag("events-fd") => {
+            error!("--events-fd expects a file descriptor, use --events-fd=<fd>");
+            return Err(());
+        },
+        None => Box::new(io::stdout()),
+        Some(fd) => Box::new(open_fd(fd)?),
+    };
+
+    Ok(Some(EventSink::new(writer)))
+}
+
+#[cfg(unix)]
+fn open_fd(fd: &str) -> Result<File, ()> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = fd.parse::<i32>().ok().filter(|fd| *fd > 2).ok_or_else(|| {
+        error!("invalid file descriptor {fd}, expected a number above 2");
+    })?;
+
+    // an inherited descriptor survived the exec, so it is open without FD_CLOEXEC,
+    // unlike the ones opened by this process, e.g. by the tokio runtime
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || flags & libc::FD_CLOEXEC != 0 {
+        error!("the file descriptor {fd} was not inherited from the parent process");
+        return Err(());
+    }
+
+    // the descriptor is opened by the parent process and owned from now on
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: &str) -> Result<File, ()> {
+    error!("--events-fd is only supported on unix");
+    Err(())
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use crate::args::Args;
+use crate::manifest_cache::{self, ManifestCache};
+use crate::models::Manifest;
+use crate::{DEFAULT_GAME, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::error;
+
+#[derive(Serialize)]
+pub struct VersionMatches {
+    pub version: String,
+    pub files: Vec<FileMatch>,
+}
+
+#[derive(Serialize)]
+pub struct FileMatch {
+    pub fragment: String,
+    pub path: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+enum Query {
+    Hash(String),
+    Path(glob::Pattern),
+}
+
+pub async fn find_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let game = args.value("game").unwrap_or(DEFAULT_GAME);
+    let platform = args.value("platform").unwrap_or(DEFAULT_PLATFORM);
+    let release = args.value("release").unwrap_or(DEFAULT_RELEASE);
+
+    let query = match args.positional(0) {
+        Some(hash) if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) => Query::Hash(hash.to_ascii_lowercase()),
+        Some(pattern) => Query::Path(glob::Pattern::new(pattern).map_err(|err| {
+            error!("invalid path pattern {pattern}: {err}");
+        })?),
+        None => {
+            error!("expected a path pattern or a sha1 to find");
+            return Err(());
+        }
+    };
+
+    let mut versions = vec![];
+    let mut index = 1;
+    while let Some(version) = args.positional(index) {
+        versions.push(version.to_string());
+        index += 1;
+    }
+
+    if versions.is_empty() {
+        if let Some(root) = ManifestCache::default_root(crate::source::source()) {
+            versions = ManifestCache::new(root).versions(game, platform, release);
+        }
+
+        let latest = crate::get_latest_version(game, platform, release).await?;
+        if !versions.contains(&latest) {
+            versions.push(latest);
+        }
+        versions.sort_by(|a, b| manifest_cache::compare_versions(a, b));
+    }
+
+    let mut results = vec![];
+    for version in versions {
+        let manifest = crate::get_manifest_cached(game, &version, platform, release).await?;
+        let files = find(&manifest, &query);
+        results.push(VersionMatches { version, files });
+    }
+
+    if args.flag("json") {
+        let json = serde_json::to_string_pretty(&results).map_err(|err| {
+            error!("could not serialize the matches: {err}");
+        })?;
+        println!("{json}");
+    } else {
+        print_matches(&results);
+    }
+
+    Ok(())
+}
+
+fn find(manifest: &Manifest, query: &Query) -> Vec<FileMatch> {
+    let mut files = vec![];
+
+    for fragment in &manifest.fragments {
+        for file in &fragment.files {
+            let matches = match query {
+                Query::Hash(hash) => &file.hash == hash,
+                Query::Path(pattern) => crate::matches_path(pattern, &file.name),
+            };
+
+            if matches {
+                files.push(FileMatch {
+                    fragment: fragment.name.clone(),
+                    path: file.name.clone(),
+                    size: file.size,
+                    hash: file.hash.clone(),
+                });
+            }
+        }
+    }
+
+    files
+}
+
+fn print_matches(results: &[VersionMatches]) {
+    for result in results {
+        if result.files.is_empty() {
+            println!("{}: not found", result.version);
+            continue;
+        }
+
+        for file in &result.files {
+            println!("{}: {}/{} ({} bytes, {})", result.version, file.fragment, file.path, file.size, file.hash);
+        }
+    }
+}
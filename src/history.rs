@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::args::Args;
+use crate::manifest_cache::{self, ManifestCache};
+use crate::models::CytrusRoot;
+use crate::source::source;
+use crate::{DEFAULT_GAME, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::{error, warn};
+
+const HISTORY_FILE: &str = "history.json";
+
+/// Every version seen in `cytrus.json`, which only lists the latest one of
+/// each release, so the older ones can still be found and downloaded.
+/// Recorded by `download`, `check-update`, `watch` and `history` only.
+#[derive(Serialize, Deserialize, Default)]
+pub struct History {
+    /// Keyed by source (see `Source::cache_key`), then by `game/platform/release`.
+    #[serde(default)]
+    sources: BTreeMap<String, BTreeMap<String, Vec<SeenVersion>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SeenVersion {
+    pub version: String,
+    /// Seconds since the unix epoch.
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    version: String,
+    first_seen: Option<u64>,
+    last_seen: Option<u64>,
+    cached: bool,
+}
+
+impl History {
+    fn path() -> Option<PathBuf> {
+        Some(crate::cache_dir()?.join(HISTORY_FILE))
+    }
+
+    pub fn load() -> History {
+        let content = match History::path().and_then(|path| fs::read(path).ok()) {
+            Some(content) => content,
+            None => return History::default(),
+        };
+
+        serde_json::from_slice(&content).unwrap_or_else(|err| {
+            warn!("ignoring the corrupted version history: {err}");
+            History::default()
+        })
+    }
+
+    /// Best effort, like the manifest cache.
+    fn save(&self) {
+        let path = match History::path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let (Some(dir), Ok(content)) = (path.parent(), serde_json::to_vec_pretty(self)) {
+            if fs::create_dir_all(dir).is_ok() {
+                let _ = crate::write_atomic(&path, &content);
+            }
+        }
+    }
+
+    pub fn versions(&self, game: &str, platform: &str, release: &str) -> &[SeenVersion] {
+        self.sources.get(&source().cache_key())
+            .and_then(|releases| releases.get(&key(game, platform, release)))
+            .map(|versions| versions.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+fn key(game: &str, platform: &str, release: &str) -> String {
+    format!("{game}/{platform}/{release}")
+}
+
+/// Adds the versions listed in the `cytrus.json` of the current source to the history.
+pub fn record(body: &CytrusRoot) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let mut history = History::load();
+    let seen_versions = history.sources.entry(source().cache_key()).or_default();
+
+    for (game_name, game) in &body.games {
+        for (platform, releases) in &game.platforms {
+            for (release, version) in releases {
+                let versions = seen_versions.entry(key(game_name, platform, release)).or_default();
+
+                match versions.iter_mut().find(|seen| &seen.version == version) {
+                    Some(seen) => seen.last_seen = now,
+                    None => versions.push(SeenVersion { version: version.clone(), first_seen: now, last_seen: now }),
+                }
+            }
+        }
+    }
+
+    history.save();
+}
+
+pub async fn history_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let game = args.positional(0).unwrap_or(DEFAULT_GAME);
+    let platform = args.positional(1).unwrap_or(DEFAULT_PLATFORM);
+    let release = args.positional(2).unwrap_or(DEFAULT_RELEASE);
+
+    // records the current version as well
+    record(&crate::get_cytrus_root().await?);
+
+    let history = History::load();
+    let cached = ManifestCache::default_root(source())
+        .map(|root| ManifestCache::new(root).versions(game, platform, release))
+        .unwrap_or_default();
+
+    let mut entries = history.versions(game, platform, release).iter()
+        .map(|seen| HistoryEntry {
+            version: seen.version.clone(),
+            first_seen: Some(seen.first_seen),
+            last_seen: Some(seen.last_seen),
+            cached: cached.contains(&seen.version),
+        })
+        .collect::<Vec<HistoryEntry>>();
+
+    // versions downloaded before the history existed, or from another source
+    for version in cached {
+        if !entries.iter().any(|entry| entry.version == version) {
+            entries.push(HistoryEntry { version, first_seen: None, last_seen: None, cached: true });
+        }
+    }
+
+    entries.sort_by(|a, b| manifest_cache::compare_versions(&a.version, &b.version));
+
+    if args.flag("json") {
+        let json = serde_json::to_string_pretty(&entries).map_err(|err| {
+            error!("could not serialize the history: {err}");
+        })?;
+        println!("{json}");
+        return Ok(());
+    }
+
+    println!("{} {} {}", game, platform, release);
+
+    for entry in &entries {
+        let mut line = format!("  {}", entry.version);
+        if let (Some(first_seen), Some(last_seen)) = (entry.first_seen, entry.last_seen) {
+            line.push_str(&format!(", seen from {} to {} (unix time)", first_seen, last_seen));
+        }
+        if entry.cached {
+            line.push_str(", manifest cached");
+        }
+        println!("{line}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1464 @@
+//! Downloads the games of the Ankama cytrus v6 cdn.
+//!
+//! The `cytrus-downloader-v6` binary is a thin command line on top of
+//! [`download`], which an application can call with its own [`DownloadOptions`],
+//! e.g. an [`Observer`] receiving the progress and a [`CancellationToken`].
+
+// the errors are logged where they happen, the callers only need to know it failed
+#![allow(clippy::result_unit_err)]
+
+use std::{env, fs};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions, remove_file};
+use std::io::{Write, Read, SeekFrom, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use crate::models::{Bundle, Chunk, CytrusRoot, FileM, Fragment, Manifest};
+use crate::manifest_cache::ManifestCache;
+use crate::progress::Progress;
+use crate::receipt::Receipt;
+use crate::source::source;
+use crate::state::HashState;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+pub use crate::chunk_store::ChunkStore;
+pub use crate::events::{Event, Observer};
+pub use crate::source::Source;
+pub use tokio_util::sync::CancellationToken;
+mod args;
+mod cat;
+#[doc(hidden)]
+pub mod cli;
+mod chunk_store;
+mod diff;
+mod events;
+mod find;
+mod history;
+mod logging;
+mod mirror;
+mod manifest_cache;
+mod models;
+mod pack;
+mod plan;
+mod progress;
+mod receipt;
+mod serve;
+mod show;
+mod source;
+mod state;
+mod watch;
+use flatbuffers::Vector;
+use crate::manifest_generated::{ManifestFb};
+use futures_util::{FutureExt, StreamExt};
+use futures_util::stream::FuturesUnordered;
+use tokio::sync::Semaphore;
+
+#[allow(dead_code, unused_imports)]
+#[path = "./flatbuffers/manifest_generated.rs"]
+mod manifest_generated;
+
+const CYTRUS_VERSION: u16 = 6;
+const CYTRUS_BASE_URL: &str = "https://cytrus.cdn.ankama.com";
+const CYTRUS_JSON: &str = "cytrus.json";
+
+const DEFAULT_GAME: &str = "dofus";
+const DEFAULT_PLATFORM: &str = "windows";
+const DEFAULT_RELEASE: &str = "main";
+
+const DEFAULT_DIR_OUT: &str = "./out";
+const DEFAULT_DIR_MIRROR: &str = "./mirror";
+
+const DEFAULT_JOBS: usize = 8;
+
+/// Bytes downloaded between two `bundle_progress` events.
+const EVENTS_PROGRESS_STEP: u64 = 1024 * 1024;
+
+/// Every game, platform and release listed by cytrus, at their latest version.
+fn get_all_targets(body: &CytrusRoot) -> Vec<Target> {
+    let mut targets = vec![];
+
+    let mut games = body.games.iter().collect::<Vec<_>>();
+    games.sort_by_key(|(name, game)| (game.order, name.to_string()));
+
+    for (game_name, game) in games {
+        let mut platforms = game.platforms.iter().collect::<Vec<_>>();
+        platforms.sort_by_key(|(name, _)| name.to_string());
+
+        for (platform, releases) in platforms {
+            let mut releases = releases.iter().collect::<Vec<_>>();
+            releases.sort();
+
+            for (release, version) in releases {
+                targets.push(Target::new(game_name, version, platform, release));
+            }
+        }
+    }
+
+    targets
+}
+
+/// Splits a `game[/platform[/release]]` target, defaulting the missing parts.
+fn parse_target(target: &str) -> Result<(String, String, String), ()> {
+    let parts = target.split('/').collect::<Vec<&str>>();
+
+    let (game, platform, release) = match parts[..] {
+        [game] => (game, DEFAULT_PLATFORM, DEFAULT_RELEASE),
+        [game, platform] => (game, platform, DEFAULT_RELEASE),
+        [game, platform, release] => (game, platform, release),
+        _ => {
+            error!("invalid target {target}, expected game/platform/release");
+            return Err(());
+        }
+    };
+
+    Ok((game.to_string(), platform.to_string(), release.to_string()))
+}
+
+/// A game version to install in its own install root, see `install_root`.
+pub struct Target {
+    pub game: String,
+    pub version: String,
+    pub platform: String,
+    pub release: String,
+}
+
+impl Target {
+    pub fn new(game: &str, version: &str, platform: &str, release: &str) -> Target {
+        Target {
+            game: game.to_string(),
+            version: version.to_string(),
+            platform: platform.to_string(),
+            release: release.to_string(),
+        }
+    }
+
+    fn root(&self, out: &Path) -> PathBuf {
+        install_root(out, &self.game, &self.platform, &self.release)
+    }
+}
+
+/// `<out>/<game>/<platform>` for the main release, `<out>/<game>/<platform>-<release>`
+/// for the others, so two releases of a game never share their files.
+fn install_root(out: &Path, game: &str, platform: &str, release: &str) -> PathBuf {
+    let root = out.join(game);
+
+    if release == DEFAULT_RELEASE {
+        root.join(platform)
+    } else {
+        root.join(format!("{platform}-{release}"))
+    }
+}
+
+/// What has to be written to bring a target up to date.
+struct Install<'a> {
+    target: &'a Target,
+    root: PathBuf,
+    state: HashState,
+    receipt: Receipt,
+    fragments: Vec<FragmentInstall>,
+}
+
+/// Files of a fragment that are missing or outdated, split between the ones
+/// extracted from the bundles holding their chunks and the ones fetched whole.
+struct FragmentInstall {
+    name: String,
+    path: PathBuf,
+    up_to_date: usize,
+    files: Vec<FileM>,
+    bundle_files: Vec<FileM>,
+    bundles: Vec<Bundle>,
+    single_files: Vec<FileM>,
+}
+
+/// How the outdated files are fetched.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DownloadMode {
+    /// Whole bundles, extracted chunk by chunk.
+    Bundles,
+    /// Each file on its own from the `hashes/` endpoint.
+    Files,
+    /// Files on their own when only a small part of their bundles is needed, bundles otherwise.
+    Auto,
+}
+
+impl DownloadMode {
+    fn parse(mode: &str) -> Result<DownloadMode, ()> {
+        match mode {
+            "bundles" => Ok(DownloadMode::Bundles),
+            "files" => Ok(DownloadMode::Files),
+            "auto" => Ok(DownloadMode::Auto),
+            _ => {
+                error!("unknown mode {mode}, expected files, bundles or auto");
+                Err(())
+            }
+        }
+    }
+}
+
+/// How `download` installs the targets, see `usage` for what each option does.
+pub struct DownloadOptions {
+    /// Hash every installed file again instead of trusting the cached hashes.
+    pub rehash: bool,
+    /// Bundles downloaded at once.
+    pub jobs: usize,
+    pub mode: DownloadMode,
+    pub chunk_store: Option<ChunkStore>,
+    /// Only print what would be downloaded and written.
+    pub dry_run: bool,
+    /// Fragments to install, all if empty.
+    pub fragments: Vec<String>,
+    pub exclude_fragments: Vec<String>,
+    /// Files to install, all if empty.
+    pub include: Vec<glob::Pattern>,
+    pub exclude: Vec<glob::Pattern>,
+    /// Receives the events of the download, `--events` or an embedding application.
+    pub observer: Option<Arc<dyn Observer>>,
+    /// Stops the download, leaving the install as it is to be resumed by the next one:
+    /// the files not reached yet keep their previous content, the ones being written
+    /// are fetched again.
+    pub cancel: CancellationToken,
+    /// Directory the games are installed in, `./out` by default.
+    pub out: PathBuf,
+    /// Where the manifests, bundles and files are fetched from, the cdn by default.
+    pub source: Source,
+    /// Draws the progress bars on the terminal, like the command line does.
+    pub progress_bars: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            rehash: false,
+            jobs: DEFAULT_JOBS,
+            mode: DownloadMode::Bundles,
+            chunk_store: None,
+            dry_run: false,
+            fragments: vec![],
+            exclude_fragments: vec![],
+            include: vec![],
+            exclude: vec![],
+            observer: None,
+            cancel: CancellationToken::new(),
+            out: PathBuf::from(DEFAULT_DIR_OUT),
+            source: Source::Http(CYTRUS_BASE_URL.to_string()),
+            progress_bars: false,
+        }
+    }
+}
+
+impl DownloadOptions {
+    fn emit(&self, event: Event) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    fn selects_fragment(&self, name: &str) -> bool {
+        (self.fragments.is_empty() || self.fragments.iter().any(|fragment| fragment == name))
+            && !self.exclude_fragments.iter().any(|fragment| fragment == name)
+    }
+
+    fn selects_file(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| matches_path(pattern, name)))
+            && !self.exclude.iter().any(|pattern| matches_path(pattern, name))
+    }
+}
+
+/// A pattern without `/` matches the file name in any directory, like `*.d2i`,
+/// the others match the whole path, like `data/common/*.d2o`.
+fn matches_path(pattern: &glob::Pattern, path: &str) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+
+    if pattern.as_str().contains('/') {
+        pattern.matches_with(path, options)
+    } else {
+        pattern.matches_with(path.rsplit('/').next().unwrap_or(path), options)
+    }
+}
+
+/// Installs the version of the game into `<out>/<game>/<platform>`, see `install_root`,
+/// only fetching what changed since the version already installed there.
+/// The errors are logged through `tracing`.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), ()> {
+/// use std::sync::Arc;
+/// use cytrus_downloader_v6::{download, DownloadOptions, Event};
+///
+/// let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<Event>();
+/// let options = DownloadOptions {
+///     out: "games".into(),
+///     observer: Some(Arc::new(sender)),
+///     ..Default::default()
+/// };
+///
+/// tokio::spawn(async move {
+///     while let Some(event) = receiver.recv().await {
+///         println!("{event:?}");
+///     }
+/// });
+///
+/// download("dofus", "6.0_2.71.3.17", "windows", "main", &options).await
+/// # }
+/// ```
+pub async fn download(game: &str, version: &str, platform: &str, release: &str, options: &DownloadOptions) -> Result<(), ()> {
+    download_targets(&[Target::new(game, version, platform, release)], options).await
+}
+
+/// Installs every target, fetching the bundles and files they share once.
+/// Ends with a `done` event.
+pub async fn download_targets(targets: &[Target], options: &DownloadOptions) -> Result<(), ()> {
+    let result = install_targets(targets, options).await;
+
+    options.emit(Event::Done { success: result.is_ok(), cancelled: options.cancel.is_cancelled() });
+
+    result
+}
+
+async fn install_targets(targets: &[Target], options: &DownloadOptions) -> Result<(), ()> {
+    let mut installs = vec![];
+
+    for target in targets {
+        if options.cancel.is_cancelled() {
+            return interrupted();
+        }
+
+        if options.dry_run {
+            info!("Planning {} version {}", target.game, target.version);
+        } else {
+            info!("Downloading {} version {}", target.game, target.version);
+        }
+        let span = info_span!("target", game = %target.game, version = %target.version, platform = %target.platform, release = %target.release);
+        let install = prepare_install(target, options).instrument(span).await;
+
+        if options.cancel.is_cancelled() {
+            return interrupted();
+        }
+        installs.push(install?);
+    }
+
+    if options.dry_run {
+        plan::print_plan(&installs, options);
+        return Ok(());
+    }
+
+    // nothing was written yet, the installs are left as they were
+    if options.cancel.is_cancelled() {
+        return interrupted();
+    }
+
+    for install in &installs {
+        create_outdated_files(install)?;
+    }
+
+    // a bundle or a file shared by several targets of the same game is only downloaded once
+    let mut bundles: BTreeMap<(&str, &str), Shared<Bundle, Destination>> = BTreeMap::new();
+    let mut single_files: BTreeMap<(&str, &str), Shared<FileM, PathBuf>> = BTreeMap::new();
+
+    for install in &installs {
+        for fragment in &install.fragments {
+            for bundle in &fragment.bundles {
+                bundles.entry((&install.target.game, &bundle.hash))
+                    .or_insert_with(|| Shared::new(bundle))
+                    .add(install, fragment, (&fragment.path, &fragment.bundle_files));
+            }
+
+            for file in &fragment.single_files {
+                single_files.entry((&install.target.game, &file.hash))
+                    .or_insert_with(|| Shared::new(file))
+                    .add(install, fragment, fragment.path.join(&file.name));
+            }
+        }
+    }
+
+    info!("Downloading {} bundles and {} files for {} targets", bundles.len(), single_files.len(), installs.len());
+
+    let progress = &Progress::new(&installs, options.observer.clone(), options.progress_bars);
+    let semaphore = Semaphore::new(options.jobs);
+    let mut futures = FuturesUnordered::new();
+
+    for ((game, _), shared) in &bundles {
+        let (bundle, destinations) = (shared.item, &shared.destinations);
+        let span = info_span!("bundle", game = %game, version = %shared.versions.join(","), fragment = %shared.fragments.join(","), bundle = %bundle.hash);
+        let semaphore = &semaphore;
+        futures.push(async move {
+            let _permit = semaphore.acquire().await.map_err(|_| ())?;
+            if options.cancel.is_cancelled() {
+                return Err(());
+            }
+
+            download_bundle(&options.source, game, destinations, bundle, options.chunk_store.as_ref(), progress, &options.cancel).await
+        }.instrument(span).boxed());
+    }
+
+    for ((game, _), shared) in &single_files {
+        let (file, destinations) = (shared.item, &shared.destinations);
+        let span = info_span!("file", game = %game, version = %shared.versions.join(","), fragment = %shared.fragments.join(","), file = %file.name);
+        let semaphore = &semaphore;
+        futures.push(async move {
+            let _permit = semaphore.acquire().await.map_err(|_| ())?;
+            if options.cancel.is_cancelled() {
+                return Err(());
+            }
+
+            download_file(&options.source, game, destinations, file, options.chunk_store.as_ref(), progress, &options.cancel).await
+        }.instrument(span).boxed());
+    }
+
+    let mut result = Ok(());
+    while let Some(res) = futures.next().await {
+        if res.is_err() {
+            result = Err(());
+        }
+    }
+    drop(futures);
+    progress.finish();
+
+    // the hash state and the receipt are left untouched, the next download
+    // finds the files that were not written and fetches them again
+    if options.cancel.is_cancelled() {
+        return interrupted();
+    }
+    result?;
+
+    for install in &mut installs {
+        for fragment in &install.fragments {
+            for file in &fragment.files {
+                install.state.record(&install.root, &format!("{}/{}", fragment.name, file.name), &file.hash);
+            }
+        }
+        install.state.save(&install.root)?;
+        install.receipt.save(&install.root)?;
+
+        info!("{} version {} installed in {}", install.target.game, install.target.version, install.root.display());
+    }
+
+    if let Some(store) = &options.chunk_store {
+        store.gc()?;
+    }
+
+    Ok(())
+}
+
+/// Fragment directory and files to extract from a bundle.
+type Destination<'a> = (&'a Path, &'a Vec<FileM>);
+
+/// A bundle or a file downloaded once, then written to every destination needing it.
+struct Shared<'a, T, D> {
+    item: &'a T,
+    destinations: Vec<D>,
+    /// Versions and fragments of the destinations, for the spans of the logs.
+    versions: Vec<&'a str>,
+    fragments: Vec<&'a str>,
+}
+
+impl<'a, T, D> Shared<'a, T, D> {
+    fn new(item: &'a T) -> Self {
+        Shared { item, destinations: vec![], versions: vec![], fragments: vec![] }
+    }
+
+    fn add(&mut self, install: &'a Install, fragment: &'a FragmentInstall, destination: D) {
+        if !self.versions.contains(&install.target.version.as_str()) {
+            self.versions.push(&install.target.version);
+        }
+        if !self.fragments.contains(&fragment.name.as_str()) {
+            self.fragments.push(&fragment.name);
+        }
+        self.destinations.push(destination);
+    }
+}
+
+fn interrupted() -> Result<(), ()> {
+    warn!("the download was interrupted, run it again to resume it");
+    Err(())
+}
+
+/// Works out what has to be fetched for the target, without writing anything.
+async fn prepare_install<'a>(target: &'a Target, options: &DownloadOptions) -> Result<Install<'a>, ()> {
+    let failed = |message: &str| {
+        if !options.cancel.is_cancelled() {
+            options.emit(Event::Error {
+                game: target.game.clone(),
+                kind: "manifest".to_string(),
+                name: target.version.clone(),
+                message: message.to_string(),
+            });
+        }
+    };
+
+    let manifest_bytes = tokio::select! {
+        bytes = get_manifest_bytes(&options.source, &target.game, &target.version, &target.platform, &target.release) => {
+            bytes.map_err(|_| failed("could not fetch the manifest"))?
+        },
+        _ = options.cancel.cancelled() => return Err(()),
+    };
+    let manifest = parse_manifest(&manifest_bytes).map_err(|_| failed("could not parse the manifest"))?;
+    info!("Manifest downloaded");
+
+    let mut receipt = Receipt::new(&target.game, &target.release, &target.platform, &target.version, &sha1_bytes(&manifest_bytes));
+
+    let out_path = target.root(&options.out);
+
+    for name in &options.fragments {
+        if !manifest.fragments.iter().any(|fragment| &fragment.name == name) {
+            warn!("{} version {} has no fragment {}", target.game, target.version, name);
+        }
+    }
+
+    let mut state = HashState::load(&out_path);
+    let mut fragments = vec![];
+
+    for mut fragment in manifest.fragments {
+        let _span = info_span!("fragment", fragment = %fragment.name).entered();
+
+        if !options.selects_fragment(&fragment.name) {
+            continue;
+        }
+
+        // only the chunks, and so the bundles, of the files kept are fetched
+        fragment.files.retain(|file| options.selects_file(&file.name));
+        if fragment.files.is_empty() && (!options.include.is_empty() || !options.exclude.is_empty()) {
+            continue;
+        }
+
+        let fragment_path = Path::join(&out_path, &fragment.name);
+
+        for file in &fragment.files {
+            receipt.files.insert(format!("{}/{}", fragment.name, file.name), file.hash.clone());
+        }
+
+        let total = fragment.files.len();
+        let files = get_outdated_files(&mut state, &out_path, &fragment.name, fragment.files, options)
+            .map_err(|_| failed("could not hash the installed files"))?;
+        let up_to_date = total - files.len();
+
+        if files.is_empty() {
+            info!("Fragment {} is already up to date", fragment.name);
+            fragments.push(FragmentInstall { name: fragment.name, path: fragment_path, up_to_date, files, bundle_files: vec![], bundles: vec![], single_files: vec![] });
+            continue;
+        }
+
+        let (bundle_files, single_files) = split_files(&files, &fragment.bundles, options.mode);
+        let bundles = get_bundles_concerned(&bundle_files, fragment.bundles);
+        info!("Fragment {}: {} files to update from {} bundles and {} single files",
+                 fragment.name, files.len(), bundles.len(), single_files.len());
+
+        fragments.push(FragmentInstall { name: fragment.name, path: fragment_path, up_to_date, files, bundle_files, bundles, single_files });
+    }
+
+    options.emit(Event::ManifestLoaded {
+        game: target.game.clone(),
+        version: target.version.clone(),
+        platform: target.platform.clone(),
+        release: target.release.clone(),
+        manifest_hash: receipt.manifest_hash.clone(),
+        fragments: fragments.len(),
+        files: fragments.iter().map(|fragment| fragment.files.len()).sum(),
+        bytes: fragments.iter().flat_map(|fragment| &fragment.files).map(|file| file.size).sum(),
+    });
+
+    Ok(Install { target, root: out_path, state, receipt, fragments })
+}
+
+/// Creates the directories of the install and its empty files. The other files
+/// extracted from bundles are only resized once their first chunk is written,
+/// see `write_chunk`, so a cancelled download leaves the ones it did not reach
+/// untouched. The files fetched whole only replace theirs once complete.
+fn create_outdated_files(install: &Install) -> Result<(), ()> {
+    create_dir_all(&install.root)?;
+
+    for fragment in &install.fragments {
+        create_dir_all(&fragment.path)?;
+
+        for file in fragment.bundle_files.iter().filter(|file| file.size == 0) {
+            let file_path = Path::join(&fragment.path, &file.name);
+            create_dir_all(file_path.parent().unwrap())?;
+
+            File::create(&file_path).map_err(|err| {
+                error!("could not create the file: {path} ({err})", path = file_path.display());
+            })?;
+        }
+    }
+
+    install.state.save(&install.root)
+}
+
+/// Files of the fragment whose installed copy is missing or does not match the manifest.
+/// Stops hashing them as soon as the download is cancelled.
+fn get_outdated_files(state: &mut HashState, root: &Path, fragment: &str, files: Vec<FileM>, options: &DownloadOptions) -> Result<Vec<FileM>, ()> {
+    let mut outdated = vec![];
+
+    for file in files {
+        if options.cancel.is_cancelled() {
+            return Err(());
+        }
+
+        let current_hash = state.hash(root, &format!("{}/{}", fragment, file.name), options.rehash)?;
+
+        if current_hash.as_deref() != Some(file.hash.as_str()) {
+            outdated.push(file);
+        }
+    }
+
+    Ok(outdated)
+}
+
+/// Bundles holding at least one chunk of the given files.
+fn get_bundles_concerned(files: &[FileM], bundles: Vec<Bundle>) -> Vec<Bundle> {
+    let mut hashes = HashSet::new();
+
+    for file in files {
+        if file.chunks.is_empty() {
+            hashes.insert(file.hash.as_str());
+        }
+
+        for chunk in &file.chunks {
+            hashes.insert(chunk.hash.as_str());
+        }
+    }
+
+    bundles.into_iter()
+        .filter(|bundle| bundle.chunks.iter().any(|chunk| hashes.contains(chunk.hash.as_str())))
+        .collect()
+}
+
+/// Splits the files between the ones extracted from bundles and the ones fetched whole.
+/// In auto mode, a file is fetched whole when less than half of every bundle
+/// holding its chunks is needed.
+fn split_files(files: &[FileM], bundles: &[Bundle], mode: DownloadMode) -> (Vec<FileM>, Vec<FileM>) {
+    // empty files are created without downloading anything
+    let (empty, files): (Vec<FileM>, Vec<FileM>) = files.iter().cloned().partition(|file| file.size == 0);
+
+    let (mut bundle_files, single_files) = match mode {
+        DownloadMode::Bundles => (files, vec![]),
+        DownloadMode::Files => (vec![], files),
+        DownloadMode::Auto => {
+            let needed = files.iter()
+                .flat_map(get_file_chunks)
+                .map(|(hash, _)| hash)
+                .collect::<HashSet<&str>>();
+
+            // bundle hash -> (bytes needed, bytes in the bundle), and chunk hash -> bundle hash
+            let mut usage: HashMap<&str, (u64, u64)> = HashMap::new();
+            let mut chunk_bundles: HashMap<&str, &str> = HashMap::new();
+
+            for bundle in bundles {
+                let entry = usage.entry(&bundle.hash).or_default();
+                for chunk in &bundle.chunks {
+                    entry.1 += chunk.size;
+                    if needed.contains(chunk.hash.as_str()) {
+                        entry.0 += chunk.size;
+                    }
+                    chunk_bundles.insert(&chunk.hash, &bundle.hash);
+                }
+            }
+
+            let sparse = |file: &FileM| get_file_chunks(file).iter().all(|(hash, _)| {
+                match chunk_bundles.get(hash).and_then(|bundle| usage.get(bundle)) {
+                    Some((needed, total)) => needed * 2 < *total,
+                    None => true,
+                }
+            });
+
+            files.into_iter().partition(|file| !sparse(file))
+        }
+    };
+
+    bundle_files.extend(empty);
+    (bundle_files, single_files)
+}
+
+/// Hash and size of the chunks making the file, a file without chunks being its own chunk.
+fn get_file_chunks(file: &FileM) -> Vec<(&str, u64)> {
+    if file.chunks.is_empty() {
+        return vec![(file.hash.as_str(), file.size)];
+    }
+
+    file.chunks.iter().map(|chunk| (chunk.hash.as_str(), chunk.size)).collect()
+}
+
+/// Downloads the whole file from the `hashes/` endpoint once and copies it to every destination.
+/// When every chunk of the file is already in the chunk store, nothing is downloaded.
+async fn download_file(source: &Source, game: &str, destinations: &[PathBuf], file: &FileM, store: Option<&ChunkStore>, progress: &Progress, cancel: &CancellationToken) -> Result<(), ()> {
+    let failed = |message: &str| {
+        if !cancel.is_cancelled() {
+            progress.emit(Event::Error { game: game.to_string(), kind: "file".to_string(), name: file.name.clone(), message: message.to_string() });
+        }
+    };
+
+    if let Some(store) = store {
+        if extract_file_from_store(destinations, file, store, progress).map_err(|_| failed("could not write the file from the chunk store"))? {
+            debug!("File {} extracted from the chunk store", file.name);
+            return Ok(());
+        }
+    }
+
+    let path = format!("{game}/hashes/{hash_pref}/{hash}",
+                       game=game, hash_pref=&file.hash[0..2], hash=file.hash);
+    let file_path = &destinations[0];
+    let part_path = &part_path(file_path);
+
+    debug!("Downloading file {} ({url})", file.name, url = source.url(&path));
+
+    create_dir_all(file_path.parent().unwrap()).map_err(|_| failed("could not create the directory of the file"))?;
+
+    let downloaded = tokio::select! {
+        result = source.download_to(&path, part_path, |_| {}) => result,
+        _ = cancel.cancelled() => Err(()),
+    };
+
+    if downloaded.is_err() {
+        failed("could not download the file");
+        let _ = remove_file(part_path);
+        return Err(());
+    }
+
+    let current_hash = sha1(part_path).map_err(|_| failed("could not hash the downloaded file"))?;
+    if current_hash != file.hash {
+        error!("the file {} is corrupted ({}, expected {})", file.name, current_hash, file.hash);
+        progress.emit(Event::VerifyFailed {
+            game: game.to_string(),
+            kind: "file".to_string(),
+            name: file.name.clone(),
+            expected: file.hash.clone(),
+            actual: current_hash,
+        });
+        failed("the downloaded file does not match its hash");
+        let _ = remove_file(part_path);
+        return Err(());
+    }
+
+    if let Some(store) = store {
+        store_file_chunks(part_path, file, store).map_err(|_| failed("could not put the file in the chunk store"))?;
+    }
+
+    rename(part_path, file_path).map_err(|_| failed("could not replace the installed file"))?;
+
+    for destination in &destinations[1..] {
+        create_dir_all(destination.parent().unwrap()).map_err(|_| failed("could not create the directory of the file"))?;
+
+        fs::copy(file_path, destination).map_err(|err| {
+            error!("could not copy the file: {path} ({err})", path = destination.display());
+            failed("could not copy the file to the other installs");
+        })?;
+    }
+
+    for destination in destinations {
+        progress.written(destination, file.size);
+    }
+
+    debug!("File {} downloaded", &file.name);
+
+    Ok(())
+}
+
+/// Downloads the bundle once and extracts its chunks into every destination.
+/// When every chunk needed is already in the chunk store, nothing is downloaded.
+async fn download_bundle(source: &Source, game: &str, destinations: &[Destination<'_>], bundle: &Bundle, store: Option<&ChunkStore>, progress: &Progress, cancel: &CancellationToken) -> Result<(), ()> {
+    let failed = |message: &str| {
+        if !cancel.is_cancelled() {
+            progress.emit(Event::Error { game: game.to_string(), kind: "bundle".to_string(), name: bundle.hash.clone(), message: message.to_string() });
+        }
+    };
+
+    if let Some(store) = store {
+        if extract_from_store(destinations, bundle, store, progress).map_err(|_| failed("could not write the files from the chunk store"))? {
+            debug!("Bundle {} extracted from the chunk store", bundle.hash);
+            return Ok(());
+        }
+    }
+
+    let bundle_path = &Path::join(destinations[0].0, &bundle.hash);
+
+    let mut up_to_date = false;
+    if bundle_path.exists() {
+        let current_hash = sha1(bundle_path).map_err(|_| failed("could not hash the bundle left by a previous download"))?;
+        if current_hash == bundle.hash {
+            debug!("Bundle {} is already downloaded", bundle.hash);
+            up_to_date = true;
+        } else {
+            // left incomplete by a previous run
+            debug!("Bundle {} is not up to date, downloading it ({}, {})", bundle.hash, current_hash, bundle.hash);
+        }
+    }
+
+    if !up_to_date {
+        let path = &format!("{game}/bundles/{}/{}", &bundle.hash[..2], &bundle.hash);
+
+        debug!("Downloading bundle {} ({url})", bundle.hash, url = source.url(path));
+
+        let size = plan::bundle_size(bundle);
+        let mut reported = 0;
+
+        progress.emit(Event::BundleStarted { game: game.to_string(), bundle: bundle.hash.clone(), size });
+
+        let download = source.download_to(path, bundle_path, |bytes| {
+            if bytes - reported >= EVENTS_PROGRESS_STEP || bytes == size {
+                reported = bytes;
+                progress.emit(Event::BundleProgress { game: game.to_string(), bundle: bundle.hash.clone(), bytes, size });
+            }
+        });
+
+        tokio::select! {
+            result = download => result.map_err(|_| failed("could not download the bundle"))?,
+            _ = cancel.cancelled() => {
+                // a partial bundle would be downloaded again anyway, its hash being wrong
+                let _ = remove_file(bundle_path);
+                return Err(());
+            }
+        }
+
+        let current_hash = sha1(bundle_path).map_err(|_| failed("could not hash the downloaded bundle"))?;
+        if current_hash != bundle.hash {
+            error!("the bundle {} is corrupted ({}, expected {})", bundle.hash, current_hash, bundle.hash);
+            progress.emit(Event::VerifyFailed {
+                game: game.to_string(),
+                kind: "bundle".to_string(),
+                name: bundle.hash.clone(),
+                expected: bundle.hash.clone(),
+                actual: current_hash,
+            });
+            failed("the downloaded bundle does not match its hash");
+            let _ = remove_file(bundle_path);
+            return Err(());
+        }
+
+        debug!("Bundle {} downloaded", bundle.hash);
+    }
+
+    let res = &bundle.chunks.iter().map(|chunk| {
+        extract_bundle_chunks(destinations, bundle_path, chunk, store, progress)
+    }).collect::<Vec<Result<(), ()>>>();
+
+    if res.iter().any(|res| res.is_err()) {
+        error!("could not extract the bundle: {path}", path = bundle_path.display());
+        failed("could not extract the bundle");
+        return Err(());
+    }
+
+    //clean the disk
+    remove_file(bundle_path).map_err(|err| {
+        error!("could not remove the file: {path} ({err})", path = bundle_path.display());
+        failed("could not remove the extracted bundle");
+    })?;
+    
+    Ok(())
+}
+
+/// Writes the chunks of the bundle needed by the destinations from the store,
+/// returns `false` without writing anything if one of them is missing.
+fn extract_from_store(destinations: &[Destination<'_>], bundle: &Bundle, store: &ChunkStore, progress: &Progress) -> Result<bool, ()> {
+    let chunks = bundle.chunks.iter()
+        .filter(|chunk| destinations.iter().any(|(_, files)| !get_files_chunks_concerned(&chunk.hash, files).is_empty()))
+        .collect::<Vec<&Chunk>>();
+
+    if !chunks.iter().all(|chunk| store.contains(&chunk.hash)) {
+        return Ok(false);
+    }
+
+    for chunk in chunks {
+        let buffer = match store.get(&chunk.hash) {
+            Some(buffer) => buffer,
+            None => return Ok(false),
+        };
+
+        for (path, files) in destinations {
+            write_chunk(path, files, chunk, &buffer, progress)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Writes the file from the chunks of the store, returns `false` without
+/// touching the destinations if one of them is missing.
+fn extract_file_from_store(destinations: &[PathBuf], file: &FileM, store: &ChunkStore, progress: &Progress) -> Result<bool, ()> {
+    let chunks = file_chunks_with_offsets(file);
+
+    if !chunks.iter().all(|chunk| store.contains(&chunk.hash)) {
+        return Ok(false);
+    }
+
+    for destination in destinations {
+        create_dir_all(destination.parent().unwrap())?;
+        File::create(part_path(destination)).map_err(|err| {
+            error!("could not create the file: {path} ({err})", path = destination.display());
+        })?;
+    }
+
+    for chunk in &chunks {
+        let buffer = match store.get(&chunk.hash) {
+            Some(buffer) => buffer,
+            None => {
+                for destination in destinations {
+                    let _ = remove_file(part_path(destination));
+                }
+                return Ok(false);
+            },
+        };
+
+        for destination in destinations {
+            write_at(&part_path(destination), chunk.offset, &buffer)?;
+            progress.written(destination, chunk.size);
+        }
+    }
+
+    for destination in destinations {
+        rename(&part_path(destination), destination)?;
+    }
+
+    Ok(true)
+}
+
+/// Adds the chunks of the downloaded file to the store.
+fn store_file_chunks(file_path: &Path, file: &FileM, store: &ChunkStore) -> Result<(), ()> {
+    let mut disk_file = File::open(file_path).map_err(|err| {
+        error!("could not open the file: {path} ({err})", path = file_path.display());
+    })?;
+
+    for chunk in file_chunks_with_offsets(file) {
+        if store.contains(&chunk.hash) {
+            continue;
+        }
+
+        let mut buffer = vec![0; chunk.size as usize];
+        disk_file.seek(SeekFrom::Start(chunk.offset))
+            .and_then(|_| disk_file.read_exact(&mut buffer))
+            .map_err(|err| {
+                error!("could not read the file: {path} ({err})", path = file_path.display());
+            })?;
+
+        store.put(&chunk.hash, &buffer)?;
+    }
+
+    Ok(())
+}
+
+/// Chunks of the file with their offset in it, a file without chunks being its own chunk.
+fn file_chunks_with_offsets(file: &FileM) -> Vec<Chunk> {
+    if file.chunks.is_empty() {
+        return vec![Chunk { size: file.size, hash: file.hash.clone(), offset: 0 }];
+    }
+
+    file.chunks.clone()
+}
+
+fn write_at(file_path: &Path, offset: u64, buffer: &[u8]) -> Result<(), ()> {
+    let mut file = OpenOptions::new().create(true).truncate(false).write(true).open(file_path).map_err(|err| {
+        error!("could not create the file: {path} ({err})", path = file_path.display());
+    })?;
+
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(buffer))
+        .map_err(|err| {
+            error!("could not write the file: {path} ({err})", path = file_path.display());
+        })
+}
+
+fn extract_bundle_chunks(destinations: &[Destination<'_>], bundle_path: &PathBuf, chunk: &Chunk, store: Option<&ChunkStore>, progress: &Progress) -> Result<(), ()> {
+    // we get the buffer chunk from the bundle
+    let mut file = File::open(bundle_path).map_err(|err| {
+        error!("could not open the bundle: {path} ({err})", path = &bundle_path.display());
+    })?;
+
+    file.seek(SeekFrom::Start(chunk.offset)).map_err(|err| {
+        error!("could not seek the bundle: {path} ({err})", path = &bundle_path.display());
+    })?;
+
+    let mut buffer = vec![0; chunk.size as usize];
+    file.read_exact(&mut buffer).map_err(|err| {
+        error!("could not read the bundle: {path} ({err})", path = &bundle_path.display());
+    })?;
+
+    if let Some(store) = store {
+        store.put(&chunk.hash, &buffer)?;
+    }
+
+    for (path, files) in destinations {
+        write_chunk(path, files, chunk, &buffer, progress)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the content of the chunk everywhere it appears in the files.
+fn write_chunk(path: &Path, files: &Vec<FileM>, chunk: &Chunk, buffer: &[u8], progress: &Progress) -> Result<(), ()> {
+    let files = get_files_chunks_concerned(&chunk.hash, files);
+
+    debug!("chunk {hash} is concerned by {nb} files", hash = chunk.hash, nb = files.len());
+    
+    // we have to write every chunks of every files
+    for (file, chunk_file) in files {
+        let file_path = Path::join(path, &file.name);
+        create_dir_all(file_path.parent().unwrap()).unwrap();
+
+        debug!("writing chunk {hash} of file {file} at {offset}..{size}",
+                                  hash = chunk.hash, file = file_path.display(), offset = chunk_file.offset, size = chunk_file.size);
+
+        let mut file_disk = OpenOptions::new().create(true).truncate(false).write(true).open(&file_path).map_err(|err| {
+            error!("could not create the file: {path} ({err})", path = &file_path.display());
+        })?;
+
+        // the previous version of the file may be longer
+        file_disk.set_len(file.size).map_err(|err| {
+            error!("could not resize the file: {path} ({err})", path = &file_path.display());
+        })?;
+        
+        file_disk.seek(SeekFrom::Start(chunk_file.offset)).map_err(|err| {
+            error!("could not seek the file: {path} ({err})", path = &file_path.display());
+        })?;
+
+        file_disk.write_all(buffer).map_err(|err| {
+            error!("could not write the file: {path} ({err})", path = &file_path.display());
+        })?;
+        
+        file_disk.flush().map_err(|err| {
+            error!("could not flush the file: {path} ({err})", path = &file_path.display());
+        })?;
+
+        progress.written(&file_path, chunk_file.size);
+    }
+
+    Ok(())
+}
+
+async fn get_latest_version(game:&str, platform:&str, release:&str) -> Result<String, ()> {
+    let body = get_cytrus_root().await?;
+    find_version(&body, game, platform, release)
+}
+
+async fn get_cytrus_root() -> Result<CytrusRoot, ()> {
+    let bytes = get_cytrus_json().await?;
+    parse_cytrus_root(&bytes)
+}
+
+async fn get_cytrus_json() -> Result<Vec<u8>, ()> {
+    source().get(CYTRUS_JSON).await
+}
+
+fn parse_cytrus_root(bytes: &[u8]) -> Result<CytrusRoot, ()> {
+    let body:CytrusRoot = serde_json::from_slice(bytes).map_err(|err| {
+        error!("could not parse the json: {}", err);
+        error!("is the url {} correct?", source().url(CYTRUS_JSON));
+    })?;
+    
+    check_cytrus_version(&body)?;
+
+    Ok(body)
+}
+
+fn check_cytrus_version(body: &CytrusRoot) -> Result<(), ()> {
+    if body.version != CYTRUS_VERSION {
+        error!("the cytrus version is not supported");
+        error!("expected {}, got {}", CYTRUS_VERSION, body.version);
+        return Err(());
+    }
+
+    Ok(())
+}
+
+fn find_version(body: &CytrusRoot, game:&str, platform:&str, release:&str) -> Result<String, ()> {
+    let game = body.games.get(game).ok_or_else(|| {
+        error!("could not find the game {}", game);
+    })?;
+    
+    let platform = game.platforms.get(platform).ok_or_else(|| {
+        error!("could not find the platform {}", platform);
+    })?;
+    
+    let release = platform.get(release).ok_or_else(|| {
+        error!("could not find the release {}", release);
+    })?;
+    
+    Ok(release.to_string())
+}
+
+async fn get_manifest(game: &str, version: &str, platform: &str, release: &str) -> Result<Manifest, ()> {
+    let bytes = get_manifest_bytes(source(), game, version, platform, release).await?;
+    parse_manifest(&bytes)
+}
+
+async fn get_manifest_bytes(source: &Source, game: &str, version: &str, platform: &str, release: &str) -> Result<Vec<u8>, ()> {
+    let bytes = source.get(&format!("{game}/releases/{release}/{platform}/{version}.manifest",
+                                      game=game, version=version, platform=platform, release=release)).await?;
+
+    if let Some(root) = ManifestCache::default_root(source) {
+        ManifestCache::new(root).put(game, version, platform, release, &bytes);
+    }
+
+    Ok(bytes)
+}
+
+/// Fails early on a version that was never released, since only the latest
+/// one of each release is listed by cytrus.
+async fn check_version_exists(game: &str, version: &str, platform: &str, release: &str) -> Result<(), ()> {
+    let cached = ManifestCache::default_root(source())
+        .is_some_and(|root| ManifestCache::new(root).get(game, version, platform, release).is_some());
+
+    let path = format!("{game}/releases/{release}/{platform}/{version}.manifest");
+    if cached || source().exists(&path).await {
+        return Ok(());
+    }
+
+    error!("{game} version {version} does not exist on {platform}/{release} ({url})", url = source().url(&path));
+    error!("run `history {game} {platform} {release}` to list the known versions");
+    Err(())
+}
+
+/// The manifest from the local cache, only fetched if it was never seen.
+async fn get_manifest_cached(game: &str, version: &str, platform: &str, release: &str) -> Result<Manifest, ()> {
+    let cached = ManifestCache::default_root(source())
+        .and_then(|root| ManifestCache::new(root).get(game, version, platform, release));
+
+    match cached {
+        Some(bytes) => parse_manifest(&bytes),
+        None => get_manifest(game, version, platform, release).await,
+    }
+}
+
+fn parse_manifest(bytes: &[u8]) -> Result<Manifest, ()> {
+    let manifest_fb = flatbuffers::root::<ManifestFb>(bytes).map_err(|err| {
+        error!("could not parse the manifest: {}", err);
+    })?;
+    
+    let mut manifest = Manifest {
+        fragments: vec![],
+    };
+    
+    match manifest_fb.fragments() {
+        Some(fragments) => {
+            for fragment_fb in fragments {
+                let mut fragment = Fragment {
+                    name: fragment_fb.name().unwrap().to_string(),
+                    files: vec![],
+                    bundles: vec![],
+                };
+                
+                match fragment_fb.files() {
+                    Some(files) => {
+                        for file_fb in files {
+                            let mut file = FileM {
+                                name: file_fb.name().unwrap().to_string(),
+                                size: file_fb.size_() as u64,
+                                // buffer to string
+                                hash: vec_to_hex_string(file_fb.hash().unwrap()),
+                                chunks: vec![],
+                                executable: file_fb.executable(),
+                                symlink: match file_fb.symlink() {
+                                    Some(symlink) => symlink.to_string(),
+                                    None => String::from(""),
+                                }
+                            };
+                            
+                            match file_fb.chunks() {
+                                Some(chunks) => {
+                                    for chunk_fb in chunks {
+                                        let chunk = Chunk {
+                                            size: chunk_fb.size_() as u64,
+                                            // buffer to string
+                                            hash: vec_to_hex_string(chunk_fb.hash().unwrap()),
+                                            offset: chunk_fb.offset() as u64,
+                                        };
+                                        
+                                        file.chunks.push(chunk);
+                                    }
+                                },
+                                None => {
+                                    //error!("could not find any chunks");
+                                    //return Err(());
+                                }
+                            }
+
+                            fragment.files.push(file);
+                        }
+                    },
+                    None => {
+                        error!("could not find any files");
+                        return Err(());
+                    }
+                }
+                
+                match fragment_fb.bundles() {
+                    Some(bundles) => {
+                        for bundle_fb in bundles {
+                            let mut bundle = Bundle {
+                                hash: vec_to_hex_string(bundle_fb.hash().unwrap()),
+                                chunks: vec![],
+                            };
+                            
+                            match bundle_fb.chunks() {
+                                Some(chunks) => {
+                                    for chunk_fb in chunks {
+                                        let chunk = Chunk {
+                                            size: chunk_fb.size_() as u64,
+                                            hash: vec_to_hex_string(chunk_fb.hash().unwrap()),
+                                            offset: chunk_fb.offset() as u64,
+                                        };
+                                        
+                                        bundle.chunks.push(chunk);
+                                    }
+                                },
+                                None => {
+                                    error!("could not find any chunks");
+                                    return Err(());
+                                }
+                            }
+
+                            fragment.bundles.push(bundle);
+                        }
+                    },
+                    None => {
+                        error!("could not find any bundles");
+                        return Err(());
+                    }
+                }
+
+                manifest.fragments.push(fragment);
+            }
+        },
+        None => {
+            error!("could not find any fragments");
+            return Err(());
+        }
+    }
+                
+    Ok(manifest)
+}
+
+fn vec_to_hex_string(vec: Vector<i8>) -> String {
+    let mut hex_string = String::new();
+    for byte in vec {
+        hex_string.push_str(&format!("{:02x}", byte as u8));
+    }
+    
+    hex_string
+}
+
+/// Client shared by every request, so they all reuse the same connection pool.
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// `$XDG_CACHE_HOME/cytrus`, falling back on `~/.cache` (or `%LOCALAPPDATA%`
+/// on windows).
+fn cache_dir() -> Option<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    Some(cache.join("cytrus"))
+}
+
+fn create_dir_all(path: &Path) -> Result<(), ()> {
+    if path.exists() {
+        return Ok(());
+    }
+    
+    debug!("creating the directory {path}", path = path.display());
+    
+    fs::create_dir_all(path).map_err(|err| {
+        error!("could not create the directory {path}: {err}", path = path.display(), err = err);
+    })
+}
+
+// Maybe use later to update the game
+#[allow(dead_code)]
+fn get_bytes_ranges(bundle:&Bundle) -> String {
+    let mut bytes = String::from("bytes=");
+    let chunks = bundle.chunks
+        .iter()
+        .map(|chunk| format!("{}-{}", chunk.offset, chunk.offset + chunk.size - 1));
+    
+    for chunk in chunks {
+        bytes.push_str(&format!("{},", chunk));
+    }
+    
+    bytes.pop();
+    bytes
+}
+
+fn sha1(file_path: &Path) -> Result<String, ()> {
+    let mut hasher = sha1_smol::Sha1::new();
+    
+    // read the file by chunks
+    let mut file = File::open(file_path).map_err(|err| {
+        error!("could not open the file {path}: {err}", path = file_path.display(), err = err);
+    })?;
+    
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let count = file.read(&mut buffer).map_err(|err| {
+            error!("could not read the file {path}: {err}", path = file_path.display(), err = err);
+        })?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    
+    Ok(hasher.digest().to_string())
+}
+
+fn sha1_bytes(bytes: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytes).digest().to_string()
+}
+
+/// Sibling a file is written to, then renamed over the file once complete.
+fn part_path(path: &Path) -> PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), ()> {
+    fs::rename(from, to).map_err(|err| {
+        error!("could not rename the file: {path} ({err})", path = from.display());
+    })
+}
+
+/// Writes the file through its `.part` sibling then renames it, so an
+/// interrupted run never leaves it truncated.
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), ()> {
+    let part_path = part_path(path);
+    fs::write(&part_path, content).map_err(|err| {
+        error!("could not write the file: {path} ({err})", path = part_path.display());
+    })?;
+
+    rename(&part_path, path)
+}
+
+fn get_files_chunks_concerned<'a>(hash:&str, files: &'a Vec<FileM>) -> Vec<(&'a FileM, Chunk)> {
+    let mut files_chunks:Vec<(&FileM, Chunk)> = vec![];
+    
+    for file in files {
+        if file.chunks.is_empty() && file.hash == hash {
+            files_chunks.push((file, Chunk {
+                size: file.size,
+                hash: file.hash.clone(),
+                offset: 0,
+            }));
+            continue;
+        }
+        
+        for chunk in &file.chunks {
+            if chunk.hash == hash {
+                files_chunks.push((file, chunk.clone()));
+            }
+        }
+    }
+
+    files_chunks
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>(_: T) {}
+
+    /// Chunks laid out one after the other, given by hash and size.
+    fn chunks(chunks: &[(&str, u64)]) -> Vec<Chunk> {
+        let mut offset = 0;
+        chunks.iter().map(|(hash, size)| {
+            offset += size;
+            Chunk { size: *size, hash: hash.to_string(), offset: offset - size }
+        }).collect()
+    }
+
+    fn file(name: &str, layout: &[(&str, u64)]) -> FileM {
+        FileM {
+            name: name.to_string(),
+            size: layout.iter().map(|(_, size)| size).sum(),
+            hash: format!("{name}-hash"),
+            chunks: chunks(layout),
+            executable: false,
+            symlink: String::new(),
+        }
+    }
+
+    fn bundle(hash: &str, layout: &[(&str, u64)]) -> Bundle {
+        Bundle { hash: hash.to_string(), chunks: chunks(layout) }
+    }
+
+    fn names(files: &[FileM]) -> Vec<&str> {
+        files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    #[test]
+    fn splits_the_files_by_mode() {
+        let files = [file("a", &[("a1", 10)]), file("empty", &[])];
+        let bundles = [bundle("A", &[("a1", 10)])];
+
+        let (bundle_files, single_files) = split_files(&files, &bundles, DownloadMode::Bundles);
+        assert_eq!((names(&bundle_files), names(&single_files)), (vec!["a", "empty"], vec![]));
+
+        // the empty files are never fetched
+        let (bundle_files, single_files) = split_files(&files, &bundles, DownloadMode::Files);
+        assert_eq!((names(&bundle_files), names(&single_files)), (vec!["empty"], vec!["a"]));
+    }
+
+    #[test]
+    fn fetches_the_files_of_sparse_bundles_on_their_own_in_auto_mode() {
+        let files = [
+            // 10 of the 100 bytes of A
+            file("sparse", &[("a1", 10)]),
+            // 60 of the 100 bytes of B
+            file("dense", &[("b1", 60)]),
+            // in A and B
+            file("both", &[("a2", 20), ("b2", 10)]),
+            // in no bundle
+            file("orphan", &[("c1", 5)]),
+        ];
+        let bundles = [
+            bundle("A", &[("a1", 10), ("a2", 20), ("a3", 70)]),
+            bundle("B", &[("b1", 60), ("b2", 10), ("b3", 30)]),
+        ];
+
+        let (bundle_files, single_files) = split_files(&files, &bundles, DownloadMode::Auto);
+
+        // A is needed for 30 bytes out of 100, B for 70
+        assert_eq!(names(&bundle_files), ["dense", "both"]);
+        assert_eq!(names(&single_files), ["sparse", "orphan"]);
+    }
+
+    #[test]
+    fn needs_exactly_half_of_a_bundle_to_download_it_in_auto_mode() {
+        let files = [file("half", &[("a1", 50)])];
+        let bundles = [bundle("A", &[("a1", 50), ("a2", 50)])];
+
+        let (bundle_files, single_files) = split_files(&files, &bundles, DownloadMode::Auto);
+        assert_eq!((names(&bundle_files), names(&single_files)), (vec!["half"], vec![]));
+    }
+
+    #[test]
+    fn download_is_send() {
+        // spawned on the multi-threaded runtime by the embedding applications
+        let options = DownloadOptions::default();
+        assert_send(download("dofus", "1.0", "windows", "main", &options));
+        assert_send(download_targets(&[], &options));
+    }
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        matches_path(&glob::Pattern::new(pattern).unwrap(), path)
+    }
+
+    #[test]
+    fn matches_the_file_name_in_any_directory_without_a_slash() {
+        assert!(matches("*.d2i", "i18n_fr.d2i"));
+        assert!(matches("*.d2i", "data/i18n/i18n_fr.d2i"));
+        assert!(matches("i18n_??.d2i", "data/i18n/i18n_fr.d2i"));
+        assert!(!matches("*.d2i", "data/i18n/i18n_fr.d2o"));
+        assert!(!matches("data", "data/i18n/i18n_fr.d2i"));
+    }
+
+    #[test]
+    fn matches_the_whole_path_with_a_slash() {
+        assert!(matches("data/common/*.d2o", "data/common/Items.d2o"));
+        assert!(!matches("data/common/*.d2o", "data/common/sub/Items.d2o"));
+        assert!(!matches("common/*.d2o", "data/common/Items.d2o"));
+        assert!(matches("data/**/*.d2o", "data/common/sub/Items.d2o"));
+        assert!(!matches("data/*", "data/common/Items.d2o"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use indicatif::MultiProgress;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Progress bars currently drawn, which the logs are printed above.
+pub fn bars() -> &'static MultiProgress {
+    BARS.get_or_init(MultiProgress::new)
+}
+
+/// Logs everything from `info` (`-q`: `error`, `-v`: `debug`, `-vv`: `trace`),
+/// unless `RUST_LOG` is set, on stderr as text or as one json object per line.
+pub fn init(verbosity: i8, json: bool) {
+    let level = match verbosity {
+        i8::MIN..=-1 => "error",
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,{}={}", env!("CARGO_CRATE_NAME"), level)));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(LogWriter);
+
+    if json {
+        builder.json().with_current_span(true).with_span_list(true).init();
+    } else {
+        builder.without_time().with_target(false).with_ansi(io::stderr().is_terminal()).init();
+    }
+}
+
+/// Writes each log line to stderr at once, with the progress bars hidden meanwhile.
+struct LogWriter;
+
+struct LogLine(Vec<u8>);
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogLine;
+
+    fn make_writer(&'a self) -> LogLine {
+        LogLine(vec![])
+    }
+}
+
+impl Write for LogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogLine {
+    fn drop(&mut self) {
+        bars().suspend(|| {
+            let _ = io::stderr().write_all(&self.0);
+        });
+    }
+}
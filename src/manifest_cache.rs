@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::PathBuf;
+use crate::source::Source;
+
+/// Local copy of every manifest fetched, laid out like the cdn as
+/// `game/releases/release/platform/version.manifest`. A version never
+/// changes once released, so a cached manifest is never refetched.
+pub struct ManifestCache {
+    root: PathBuf,
+}
+
+impl ManifestCache {
+    pub fn new(root: PathBuf) -> ManifestCache {
+        ManifestCache { root }
+    }
+
+    /// `$XDG_CACHE_HOME/cytrus/manifests/<source>`, next to the chunk store,
+    /// one cache per source as a mirror may hold versions the cdn never had.
+    pub fn default_root(source: &Source) -> Option<PathBuf> {
+        Some(crate::cache_dir()?.join("manifests").join(source.cache_key()))
+    }
+
+    fn release_dir(&self, game: &str, platform: &str, release: &str) -> PathBuf {
+        self.root.join(game).join("releases").join(release).join(platform)
+    }
+
+    pub fn get(&self, game: &str, version: &str, platform: &str, release: &str) -> Option<Vec<u8>> {
+        fs::read(self.release_dir(game, platform, release).join(format!("{version}.manifest"))).ok()
+    }
+
+    /// Best effort, a manifest that could not be cached is simply fetched again.
+    pub fn put(&self, game: &str, version: &str, platform: &str, release: &str, bytes: &[u8]) {
+        let dir = self.release_dir(game, platform, release);
+        let path = dir.join(format!("{version}.manifest"));
+
+        if path.exists() || fs::create_dir_all(&dir).is_err() {
+            return;
+        }
+
+        let _ = crate::write_atomic(&path, bytes);
+    }
+
+    /// Versions cached for the release, oldest first.
+    pub fn versions(&self, game: &str, platform: &str, release: &str) -> Vec<String> {
+        let mut versions = fs::read_dir(self.release_dir(game, platform, release)).into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".manifest").map(|version| version.to_string()))
+            .collect::<Vec<String>>();
+
+        versions.sort_by(|a, b| compare_versions(a, b));
+        versions
+    }
+}
+
+/// Orders versions like `6.0_2.71.3.17` by their numbers, not their text.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| version.split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u64>().unwrap_or(u64::MAX))
+        .collect::<Vec<u64>>();
+
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use super::compare_versions;
+
+    #[test]
+    fn compares_versions_by_their_numbers() {
+        assert_eq!(compare_versions("6.0_2.71.3.17", "6.0_2.71.3.9"), Ordering::Greater);
+        assert_eq!(compare_versions("6.0_2.9.0.0", "6.0_2.10.0.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn sorts_versions() {
+        let mut versions = vec!["5.0_2.10.1", "5.0_2.9.3", "6.0_2.0.0", "5.0_2.10.0"];
+        versions.sort_by(|a, b| compare_versions(a, b));
+
+        assert_eq!(versions, ["5.0_2.9.3", "5.0_2.10.0", "5.0_2.10.1", "6.0_2.0.0"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_their_text() {
+        assert_eq!(compare_versions("1.0-beta", "1.0-alpha"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1_0"), "1.0".cmp("1_0"));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use tokio::sync::Semaphore;
+use crate::args::Args;
+use crate::models::{Bundle, CytrusRoot, FileM, GameRoot};
+use crate::source::Source;
+use crate::{Target, CYTRUS_JSON, DEFAULT_DIR_MIRROR, DEFAULT_GAME};
+use tracing::{debug, error, info};
+
+pub async fn mirror_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let dir = PathBuf::from(args.value("dir").unwrap_or(DEFAULT_DIR_MIRROR));
+    let jobs = crate::cli::jobs_from_args(&args)?;
+
+    let body = crate::get_cytrus_root().await?;
+
+    let mut targets = vec![];
+
+    if args.flag("all") {
+        targets.extend(crate::get_all_targets(&body));
+    }
+
+    let mut index = 0;
+    while let Some(target) = args.positional(index) {
+        let (game, platform, release) = crate::parse_target(target)?;
+        let version = crate::find_version(&body, &game, &platform, &release)?;
+        targets.push(Target::new(&game, &version, &platform, &release));
+        index += 1;
+    }
+
+    if targets.is_empty() {
+        let (game, platform, release) = crate::parse_target(DEFAULT_GAME)?;
+        let version = crate::find_version(&body, &game, &platform, &release)?;
+        targets.push(Target::new(&game, &version, &platform, &release));
+    }
+
+    crate::create_dir_all(&dir)?;
+    mirror(&dir, &targets, jobs).await?;
+
+    // written last, so the mirror never lists a version whose bundles are missing
+    update_cytrus_json(&dir, &body, &targets)
+}
+
+/// Lists the mirrored targets in the `cytrus.json` of the mirror, next to the
+/// ones mirrored by the previous runs.
+fn update_cytrus_json(dir: &Path, upstream: &CytrusRoot, targets: &[Target]) -> Result<(), ()> {
+    let path = dir.join(CYTRUS_JSON);
+
+    let mut root = match fs::read(&path) {
+        Ok(content) => serde_json::from_slice::<CytrusRoot>(&content).map_err(|err| {
+            error!("could not parse the json {path}: {err}", path = path.display());
+        })?,
+        Err(_) => CytrusRoot {
+            name: upstream.name.clone(),
+            version: upstream.version,
+            games: HashMap::new(),
+        },
+    };
+
+    for target in targets {
+        let game = root.games.entry(target.game.clone()).or_insert_with(|| GameRoot {
+            platforms: HashMap::new(),
+            ..upstream.games[&target.game].clone()
+        });
+
+        game.platforms.entry(target.platform.clone())
+            .or_default()
+            .insert(target.release.clone(), target.version.clone());
+    }
+
+    let content = serde_json::to_vec_pretty(&root).map_err(|err| {
+        error!("could not serialize the json: {err}");
+    })?;
+
+    crate::write_atomic(&path, &content)
+}
+
+/// Copies the manifests and the raw bundles of the targets into `dir`, with
+/// the cdn layout, then writes their files under `hashes/` for the downloads
+/// fetching files on their own and `cat`. What is already mirrored is skipped.
+async fn mirror(dir: &Path, targets: &[Target], jobs: usize) -> Result<(), ()> {
+    let mut bundles: BTreeMap<(String, String), Bundle> = BTreeMap::new();
+    let mut files: BTreeMap<(String, String), FileM> = BTreeMap::new();
+    // (game, chunk hash) -> (bundle hash, offset in the bundle)
+    let mut locations: HashMap<(String, String), (String, u64)> = HashMap::new();
+
+    for target in targets {
+        info!("Mirroring {} version {} ({} {})", target.game, target.version, target.platform, target.release);
+
+        let manifest_bytes = crate::get_manifest_bytes(crate::source::source(), &target.game, &target.version, &target.platform, &target.release).await?;
+        let manifest = crate::parse_manifest(&manifest_bytes)?;
+
+        let manifest_dir = dir.join(&target.game).join("releases").join(&target.release).join(&target.platform);
+        crate::create_dir_all(&manifest_dir)?;
+        crate::write_atomic(&manifest_dir.join(format!("{}.manifest", target.version)), &manifest_bytes)?;
+
+        for fragment in manifest.fragments {
+            for bundle in fragment.bundles {
+                for chunk in &bundle.chunks {
+                    locations.insert((target.game.clone(), chunk.hash.clone()), (bundle.hash.clone(), chunk.offset));
+                }
+                bundles.entry((target.game.clone(), bundle.hash.clone())).or_insert(bundle);
+            }
+
+            // nothing is fetched for the empty files and the symlinks
+            for file in fragment.files.into_iter().filter(|file| file.size > 0 && file.symlink.is_empty()) {
+                files.entry((target.game.clone(), file.hash.clone())).or_insert(file);
+            }
+        }
+    }
+
+    let missing = bundles.iter()
+        .filter(|((game, hash), _)| !bundle_path(dir, game, hash).exists())
+        .collect::<Vec<_>>();
+
+    info!("{} bundles, {} already mirrored", bundles.len(), bundles.len() - missing.len());
+
+    let semaphore = Semaphore::new(jobs);
+    let mut futures = FuturesUnordered::new();
+
+    for ((game, _), bundle) in missing {
+        let semaphore = &semaphore;
+        futures.push(async move {
+            let _permit = semaphore.acquire().await.map_err(|_| ())?;
+            mirror_bundle(dir, game, bundle).await
+        });
+    }
+
+    let mut result = Ok(());
+    while let Some(res) = futures.next().await {
+        if res.is_err() {
+            result = Err(());
+        }
+    }
+    result?;
+
+    let missing = files.iter()
+        .filter(|((game, hash), _)| !hash_path(dir, game, hash).exists())
+        .collect::<Vec<_>>();
+
+    info!("{} files, {} already mirrored", files.len(), files.len() - missing.len());
+
+    // read back from the bundles just mirrored
+    let mirrored = Source::Local(dir.to_path_buf());
+    let mut result = Ok(());
+
+    for ((game, _), file) in missing {
+        if mirror_file(dir, &mirrored, game, file, &locations).await.is_err() {
+            result = Err(());
+        }
+    }
+
+    result
+}
+
+fn bundle_path(dir: &Path, game: &str, hash: &str) -> PathBuf {
+    dir.join(game).join("bundles").join(&hash[..2]).join(hash)
+}
+
+fn hash_path(dir: &Path, game: &str, hash: &str) -> PathBuf {
+    dir.join(game).join("hashes").join(&hash[..2]).join(hash)
+}
+
+async fn mirror_bundle(dir: &Path, game: &str, bundle: &Bundle) -> Result<(), ()> {
+    let path = format!("{game}/bundles/{}/{}", &bundle.hash[..2], &bundle.hash);
+    fetch_verified(&path, &bundle_path(dir, game, &bundle.hash), &bundle.hash).await
+}
+
+/// Writes the file under `hashes/` from the chunks of the mirrored bundles, or
+/// fetches it from the source when one of them is in no bundle.
+async fn mirror_file(dir: &Path, mirrored: &Source, game: &str, file: &FileM, locations: &HashMap<(String, String), (String, u64)>) -> Result<(), ()> {
+    let path = hash_path(dir, game, &file.hash);
+    let chunks = crate::file_chunks_with_offsets(file);
+
+    if !chunks.iter().all(|chunk| locations.contains_key(&(game.to_string(), chunk.hash.clone()))) {
+        let source_path = format!("{game}/hashes/{}/{}", &file.hash[..2], &file.hash);
+        return fetch_verified(&source_path, &path, &file.hash).await;
+    }
+
+    debug!("Writing file {} from the bundles", file.name);
+
+    let part_path = crate::part_path(&path);
+    crate::create_dir_all(path.parent().unwrap())?;
+    fs::File::create(&part_path).map_err(|err| {
+        error!("could not create the file: {path} ({err})", path = part_path.display());
+    })?;
+
+    for chunk in &chunks {
+        let (bundle, offset) = &locations[&(game.to_string(), chunk.hash.clone())];
+        let bundle_path = format!("{game}/bundles/{}/{}", &bundle[..2], bundle);
+
+        let written = match mirrored.get_range(&bundle_path, *offset, chunk.size).await {
+            Ok(bytes) => crate::write_at(&part_path, chunk.offset, &bytes),
+            Err(()) => Err(()),
+        };
+
+        if written.is_err() {
+            let _ = fs::remove_file(&part_path);
+            return Err(());
+        }
+    }
+
+    verify(&part_path, &file.hash)?;
+    crate::rename(&part_path, &path)
+}
+
+/// Streams the file at `path` from the source to `destination` through a
+/// `.part` sibling, only renamed once its content matches `hash`.
+async fn fetch_verified(path: &str, destination: &Path, hash: &str) -> Result<(), ()> {
+    let source = crate::source::source();
+    let part_path = crate::part_path(destination);
+
+    debug!("Downloading {} ({url})", hash, url = source.url(path));
+
+    crate::create_dir_all(destination.parent().unwrap())?;
+
+    if source.download_to(path, &part_path, |_| {}).await.is_err() {
+        let _ = fs::remove_file(&part_path);
+        return Err(());
+    }
+
+    verify(&part_path, hash)?;
+    crate::rename(&part_path, destination)
+}
+
+/// Removes the file when its content does not match `hash`.
+fn verify(path: &Path, hash: &str) -> Result<(), ()> {
+    let current_hash = crate::sha1(path)?;
+
+    if current_hash != hash {
+        error!("{path} is corrupted ({current_hash}, expected {hash})", path = path.display());
+        let _ = fs::remove_file(path);
+        return Err(());
+    }
+
+    Ok(())
+}
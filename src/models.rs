@@ -1,14 +1,14 @@
 use std::collections::HashMap;
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CytrusRoot {
     pub name: String,
     pub version: u16,
     pub games: HashMap<String, GameRoot>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GameRoot {
     pub name: String,
     pub order: u16,
@@ -17,17 +17,19 @@ pub struct GameRoot {
     pub platforms: HashMap<String, HashMap<String, String>>,
 }
 
-
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Manifest {
     pub fragments: Vec<Fragment>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Fragment {
     pub name: String,
     pub files: Vec<FileM>,
     pub bundles: Vec<Bundle>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileM {
     pub name: String,
     pub size: u64,
@@ -37,11 +39,13 @@ pub struct FileM {
     pub symlink: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bundle {
     pub hash: String,
     pub chunks: Vec<Chunk>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Chunk {
     pub size: u64,
     pub hash: String,
@@ -0,0 +1,532 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use glob::{MatchOptions, Pattern};
+use crate::args::{self, Args};
+use crate::models::{Bundle, Chunk, CytrusRoot, FileM, Fragment, GameRoot, Manifest};
+use crate::manifest_generated::{BundleFb, BundleFbArgs, ChunkFb, ChunkFbArgs, FileFb, FileFbArgs, FragmentFb, FragmentFbArgs, ManifestFb, ManifestFbArgs};
+use crate::state::STATE_DIR;
+use crate::{CYTRUS_VERSION, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::{error, info};
+
+const DEFAULT_CHUNK_SIZE: u64 = 1024 * 1024;
+const DEFAULT_BUNDLE_SIZE: u64 = 16 * 1024 * 1024;
+const DEFAULT_FRAGMENT: &str = "main";
+
+struct PackOptions<'a> {
+    dir: &'a Path,
+    out: &'a Path,
+    game: &'a str,
+    version: &'a str,
+    platform: &'a str,
+    release: &'a str,
+    fragments: Vec<(String, Pattern)>,
+    chunk_size: u64,
+    bundle_size: u64,
+    hashes: bool,
+}
+
+/// Where the content of a chunk can be read while writing the bundles.
+struct ChunkSource {
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+}
+
+pub fn pack_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+
+    let (dir, out, game, version) = match (args.positional(0), args.positional(1), args.positional(2), args.positional(3)) {
+        (Some(dir), Some(out), Some(game), Some(version)) => (dir, out, game, version),
+        _ => {
+            error!("expected the directory to pack, the output directory, the game and the version");
+            return Err(());
+        }
+    };
+
+    let mut fragments = vec![];
+    for mapping in args.values("fragment") {
+        let (name, pattern) = mapping.split_once(':').ok_or_else(|| {
+            error!("invalid fragment mapping {mapping}, expected <name>:<glob>");
+        })?;
+
+        let pattern = Pattern::new(pattern).map_err(|err| {
+            error!("invalid path pattern {pattern}: {err}");
+        })?;
+
+        fragments.push((name.to_string(), pattern));
+    }
+
+    let size = |name, default| match args.value(name) {
+        Some(size) => args::parse_size(size).and_then(|size| match size {
+            0 => {
+                error!("the {name} can not be 0");
+                Err(())
+            },
+            size => Ok(size),
+        }),
+        None => Ok(default),
+    };
+
+    pack(&PackOptions {
+        dir: Path::new(dir),
+        out: Path::new(out),
+        game,
+        version,
+        platform: args.positional(4).unwrap_or(DEFAULT_PLATFORM),
+        release: args.positional(5).unwrap_or(DEFAULT_RELEASE),
+        fragments,
+        chunk_size: size("chunk-size", DEFAULT_CHUNK_SIZE)?,
+        bundle_size: size("bundle-size", DEFAULT_BUNDLE_SIZE)?,
+        hashes: args.flag("hashes"),
+    })
+}
+
+fn pack(options: &PackOptions) -> Result<(), ()> {
+    let game_path = options.out.join(options.game);
+
+    // fragment name -> (path in the fragment, path on the disk)
+    let mut fragment_files: BTreeMap<String, Vec<(String, PathBuf)>> = BTreeMap::new();
+
+    let match_options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+
+    for (name, path) in walk(options.dir)? {
+        if name == STATE_DIR || name.starts_with(&format!("{STATE_DIR}/")) {
+            continue;
+        }
+
+        let mapped = options.fragments.iter().find(|(_, pattern)| pattern.matches_with(&name, match_options));
+
+        // without mappings, the top level directories are the fragments
+        let (fragment, name) = match mapped {
+            Some((fragment, _)) => (fragment.clone(), name),
+            None if !options.fragments.is_empty() => (DEFAULT_FRAGMENT.to_string(), name),
+            None => match name.split_once('/') {
+                Some((fragment, name)) => (fragment.to_string(), name.to_string()),
+                None => (DEFAULT_FRAGMENT.to_string(), name),
+            },
+        };
+
+        fragment_files.entry(fragment).or_default().push((name, path));
+    }
+
+    let mut manifest = Manifest { fragments: vec![] };
+
+    for (name, files) in fragment_files {
+        info!("Packing fragment {} ({} files)", name, files.len());
+
+        let mut sources: HashMap<String, ChunkSource> = HashMap::new();
+        let mut order = vec![];
+        let mut fragment = Fragment { name, files: vec![], bundles: vec![] };
+
+        for (name, path) in files {
+            let file = chunk_file(&name, &path, options.chunk_size, &mut sources, &mut order)?;
+
+            if options.hashes && file.symlink.is_empty() {
+                let hash_path = game_path.join("hashes").join(&file.hash[..2]).join(&file.hash);
+                if !hash_path.exists() {
+                    crate::create_dir_all(hash_path.parent().unwrap())?;
+                    fs::copy(&path, &hash_path).map_err(|err| {
+                        error!("could not copy the file: {path} ({err})", path = path.display());
+                    })?;
+                }
+            }
+
+            fragment.files.push(file);
+        }
+
+        fragment.bundles = write_bundles(&game_path.join("bundles"), &order, &sources, options.bundle_size)?;
+        info!("Fragment {}: {} chunks in {} bundles", fragment.name, order.len(), fragment.bundles.len());
+
+        manifest.fragments.push(fragment);
+    }
+
+    let manifest_dir = game_path.join("releases").join(options.release).join(options.platform);
+    crate::create_dir_all(&manifest_dir)?;
+
+    let manifest_path = manifest_dir.join(format!("{}.manifest", options.version));
+    crate::write_atomic(&manifest_path, &build_manifest(&manifest)?)?;
+    info!("Manifest written to {}", manifest_path.display());
+
+    update_cytrus_json(options)
+}
+
+/// Every file under `dir` (symlinks included, not followed), with its path relative to `dir`.
+fn walk(dir: &Path) -> Result<Vec<(String, PathBuf)>, ()> {
+    let mut files = vec![];
+    let mut dirs = vec![(String::new(), dir.to_path_buf())];
+
+    while let Some((prefix, dir)) = dirs.pop() {
+        let entries = fs::read_dir(&dir).map_err(|err| {
+            error!("could not read the directory {path}: {err}", path = dir.display());
+        })?;
+
+        for entry in entries {
+            let entry = entry.map_err(|err| {
+                error!("could not read the directory {path}: {err}", path = dir.display());
+            })?;
+
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            let file_type = entry.file_type().map_err(|err| {
+                error!("could not read the file {path}: {err}", path = entry.path().display());
+            })?;
+
+            if file_type.is_dir() {
+                dirs.push((format!("{name}/"), entry.path()));
+            } else {
+                files.push((name, entry.path()));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Describes the file, registering the chunks not seen yet in `sources`.
+/// A file fitting in a single chunk has no chunks, it is its own chunk.
+fn chunk_file(name: &str, path: &Path, chunk_size: u64, sources: &mut HashMap<String, ChunkSource>, order: &mut Vec<String>) -> Result<FileM, ()> {
+    let metadata = fs::symlink_metadata(path).map_err(|err| {
+        error!("could not read the file {path}: {err}", path = path.display());
+    })?;
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path).map_err(|err| {
+            error!("could not read the link {path}: {err}", path = path.display());
+        })?;
+
+        return Ok(FileM {
+            name: name.to_string(),
+            size: 0,
+            hash: crate::sha1_bytes(&[]),
+            chunks: vec![],
+            executable: false,
+            symlink: target.to_string_lossy().replace('\\', "/"),
+        });
+    }
+
+    let mut file = File::open(path).map_err(|err| {
+        error!("could not open the file {path}: {err}", path = path.display());
+    })?;
+
+    let mut hasher = sha1_smol::Sha1::new();
+    let mut chunks = vec![];
+    let mut buffer = vec![0; chunk_size as usize];
+    let mut offset = 0;
+
+    loop {
+        let count = read_full(&mut file, &mut buffer).map_err(|err| {
+            error!("could not read the file {path}: {err}", path = path.display());
+        })?;
+        if count == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..count]);
+        chunks.push(Chunk {
+            size: count as u64,
+            hash: crate::sha1_bytes(&buffer[..count]),
+            offset,
+        });
+        offset += count as u64;
+    }
+
+    let hash = hasher.digest().to_string();
+
+    if chunks.len() <= 1 {
+        chunks.clear();
+        register(sources, order, &hash, path, 0, offset);
+    } else {
+        for chunk in &chunks {
+            register(sources, order, &chunk.hash, path, chunk.offset, chunk.size);
+        }
+    }
+
+    Ok(FileM {
+        name: name.to_string(),
+        size: offset,
+        hash,
+        chunks,
+        executable: is_executable(&metadata),
+        symlink: String::new(),
+    })
+}
+
+fn register(sources: &mut HashMap<String, ChunkSource>, order: &mut Vec<String>, hash: &str, path: &Path, offset: u64, size: u64) {
+    // empty files are created without downloading anything
+    if size == 0 || sources.contains_key(hash) {
+        return;
+    }
+
+    sources.insert(hash.to_string(), ChunkSource { path: path.to_path_buf(), offset, size });
+    order.push(hash.to_string());
+}
+
+/// Reads until the buffer is full or the end of the file is reached.
+fn read_full(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut count = 0;
+
+    while count < buffer.len() {
+        match file.read(&mut buffer[count..])? {
+            0 => break,
+            read => count += read,
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Groups the chunks, in order, into bundles of at most `bundle_size` bytes
+/// (a bigger chunk gets its own bundle) written to `bundles/xx/hash`.
+fn write_bundles(bundles_path: &Path, order: &[String], sources: &HashMap<String, ChunkSource>, bundle_size: u64) -> Result<Vec<Bundle>, ()> {
+    let mut bundles = vec![];
+    let mut buffer = vec![];
+    let mut chunks = vec![];
+
+    for hash in order {
+        let source = &sources[hash];
+
+        if !buffer.is_empty() && buffer.len() as u64 + source.size > bundle_size {
+            bundles.push(write_bundle(bundles_path, &buffer, chunks)?);
+            buffer.clear();
+            chunks = vec![];
+        }
+
+        let mut file = File::open(&source.path).map_err(|err| {
+            error!("could not open the file {path}: {err}", path = source.path.display());
+        })?;
+
+        file.seek(SeekFrom::Start(source.offset)).map_err(|err| {
+            error!("could not seek the file {path}: {err}", path = source.path.display());
+        })?;
+
+        let start = buffer.len();
+        buffer.resize(start + source.size as usize, 0);
+        file.read_exact(&mut buffer[start..]).map_err(|err| {
+            error!("could not read the file {path}: {err}", path = source.path.display());
+        })?;
+
+        chunks.push(Chunk {
+            size: source.size,
+            hash: hash.clone(),
+            offset: start as u64,
+        });
+    }
+
+    if !buffer.is_empty() {
+        bundles.push(write_bundle(bundles_path, &buffer, chunks)?);
+    }
+
+    Ok(bundles)
+}
+
+fn write_bundle(bundles_path: &Path, content: &[u8], chunks: Vec<Chunk>) -> Result<Bundle, ()> {
+    let hash = crate::sha1_bytes(content);
+    let dir = bundles_path.join(&hash[..2]);
+    let path = dir.join(&hash);
+
+    if !path.exists() {
+        crate::create_dir_all(&dir)?;
+        crate::write_atomic(&path, content)?;
+    }
+
+    Ok(Bundle { hash, chunks })
+}
+
+/// Adds the packed version to the `cytrus.json` of the output, creating it if needed.
+fn update_cytrus_json(options: &PackOptions) -> Result<(), ()> {
+    let path = options.out.join("cytrus.json");
+
+    let mut root = match fs::read(&path) {
+        Ok(content) => serde_json::from_slice::<CytrusRoot>(&content).map_err(|err| {
+            error!("could not parse the json {path}: {err}", path = path.display());
+        })?,
+        Err(_) => CytrusRoot {
+            name: String::from("local"),
+            version: CYTRUS_VERSION,
+            games: HashMap::new(),
+        },
+    };
+
+    root.games.entry(options.game.to_string())
+        .or_insert_with(|| GameRoot {
+            name: options.game.to_string(),
+            order: 0,
+            game_id: 0,
+            platforms: HashMap::new(),
+        })
+        .platforms.entry(options.platform.to_string())
+        .or_default()
+        .insert(options.release.to_string(), options.version.to_string());
+
+    let content = serde_json::to_vec_pretty(&root).map_err(|err| {
+        error!("could not serialize the json: {err}");
+    })?;
+
+    crate::write_atomic(&path, &content)
+}
+
+fn hex_string_to_vec(hex_string: &str) -> Result<Vec<i8>, ()> {
+    (0..hex_string.len()).step_by(2)
+        .map(|index| hex_string.get(index..index + 2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .map(|byte| byte as i8)
+            .ok_or_else(|| {
+                error!("invalid hash: {hex_string}");
+            }))
+        .collect()
+}
+
+/// Encodes the manifest as a `ManifestFb` flatbuffer, the inverse of `parse_manifest`.
+fn build_manifest(manifest: &Manifest) -> Result<Vec<u8>, ()> {
+    let mut fbb = flatbuffers::FlatBufferBuilder::new();
+
+    fn build_chunks<'a>(fbb: &mut flatbuffers::FlatBufferBuilder<'a>, chunks: &[Chunk]) -> Result<Vec<flatbuffers::WIPOffset<ChunkFb<'a>>>, ()> {
+        let mut chunks_fb = vec![];
+
+        for chunk in chunks {
+            let hash = hex_string_to_vec(&chunk.hash)?;
+            let hash = fbb.create_vector(&hash);
+            chunks_fb.push(ChunkFb::create(fbb, &ChunkFbArgs {
+                hash: Some(hash),
+                size_: chunk.size as i64,
+                offset: chunk.offset as i64,
+            }));
+        }
+
+        Ok(chunks_fb)
+    }
+
+    let mut fragments_fb = vec![];
+
+    for fragment in &manifest.fragments {
+        let mut files_fb = vec![];
+
+        for file in &fragment.files {
+            let name = fbb.create_string(&file.name);
+            let hash = hex_string_to_vec(&file.hash)?;
+            let hash = fbb.create_vector(&hash);
+            let chunks = build_chunks(&mut fbb, &file.chunks)?;
+            let chunks = fbb.create_vector(&chunks);
+            let symlink = match file.symlink.is_empty() {
+                true => None,
+                false => Some(fbb.create_string(&file.symlink)),
+            };
+
+            files_fb.push(FileFb::create(&mut fbb, &FileFbArgs {
+                name: Some(name),
+                size_: file.size as i64,
+                hash: Some(hash),
+                chunks: Some(chunks),
+                executable: file.executable,
+                symlink,
+            }));
+        }
+
+        let mut bundles_fb = vec![];
+
+        for bundle in &fragment.bundles {
+            let hash = hex_string_to_vec(&bundle.hash)?;
+            let hash = fbb.create_vector(&hash);
+            let chunks = build_chunks(&mut fbb, &bundle.chunks)?;
+            let chunks = fbb.create_vector(&chunks);
+
+            bundles_fb.push(BundleFb::create(&mut fbb, &BundleFbArgs {
+                hash: Some(hash),
+                chunks: Some(chunks),
+            }));
+        }
+
+        let name = fbb.create_string(&fragment.name);
+        let files = fbb.create_vector(&files_fb);
+        let bundles = fbb.create_vector(&bundles_fb);
+
+        fragments_fb.push(FragmentFb::create(&mut fbb, &FragmentFbArgs {
+            name: Some(name),
+            files: Some(files),
+            bundles: Some(bundles),
+        }));
+    }
+
+    let fragments = fbb.create_vector(&fragments_fb);
+    let manifest_fb = ManifestFb::create(&mut fbb, &ManifestFbArgs {
+        fragments: Some(fragments),
+    });
+
+    fbb.finish(manifest_fb, None);
+    Ok(fbb.finished_data().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Bundle, Chunk, FileM, Fragment, Manifest};
+    use super::build_manifest;
+
+    fn chunk(hash: &str, offset: u64, size: u64) -> Chunk {
+        Chunk { size, hash: hash.repeat(20), offset }
+    }
+
+    #[test]
+    fn builds_manifests_parsed_back_unchanged() {
+        let manifest = Manifest {
+            fragments: vec![
+                Fragment {
+                    name: "main".to_string(),
+                    files: vec![
+                        FileM {
+                            name: "Dofus.exe".to_string(),
+                            size: 30,
+                            hash: "0a".repeat(20),
+                            chunks: vec![chunk("1b", 0, 10), chunk("2c", 10, 20)],
+                            executable: true,
+                            symlink: String::new(),
+                        },
+                        FileM {
+                            name: "data/small.txt".to_string(),
+                            size: 5,
+                            hash: "3d".repeat(20),
+                            chunks: vec![],
+                            executable: false,
+                            symlink: String::new(),
+                        },
+                        FileM {
+                            name: "current".to_string(),
+                            size: 0,
+                            hash: "4e".repeat(20),
+                            chunks: vec![],
+                            executable: false,
+                            symlink: "data/small.txt".to_string(),
+                        },
+                    ],
+                    bundles: vec![
+                        Bundle {
+                            hash: "5f".repeat(20),
+                            chunks: vec![chunk("1b", 0, 10), chunk("2c", 10, 20), chunk("3d", 30, 5)],
+                        },
+                    ],
+                },
+                Fragment {
+                    name: "empty".to_string(),
+                    files: vec![],
+                    bundles: vec![],
+                },
+            ],
+        };
+
+        let bytes = build_manifest(&manifest).unwrap();
+
+        assert_eq!(crate::parse_manifest(&bytes), Ok(manifest));
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use crate::models::{Bundle, FileM};
+use crate::{DownloadOptions, FragmentInstall, Install};
+
+/// Prints what `download` would fetch and write for the installs, as given by
+/// `--dry-run`. Bundles and files shared by several targets are counted once.
+pub fn print_plan(installs: &[Install], options: &DownloadOptions) {
+    let mut bundles: BTreeMap<(&str, &str), (&Bundle, HashSet<&str>)> = BTreeMap::new();
+    let mut single_files: BTreeMap<(&str, &str), &FileM> = BTreeMap::new();
+    let mut write_bytes = 0;
+    let mut disk_bytes: i64 = 0;
+
+    for install in installs {
+        let target = install.target;
+        println!("{} version {} ({} {}) in {}", target.game, target.version, target.platform, target.release, install.root.display());
+
+        for fragment in &install.fragments {
+            print_fragment(fragment);
+
+            for bundle in &fragment.bundles {
+                bundles.entry((&target.game, &bundle.hash))
+                    .or_insert_with(|| (bundle, HashSet::new()))
+                    .1.extend(needed_chunks(bundle, &fragment.bundle_files));
+            }
+
+            for file in &fragment.single_files {
+                single_files.entry((&target.game, &file.hash)).or_insert(file);
+            }
+
+            for file in &fragment.files {
+                // the outdated files are truncated, only the growth takes new space
+                let current = fs::symlink_metadata(fragment.path.join(&file.name)).map(|metadata| metadata.len()).unwrap_or(0);
+                write_bytes += file.size;
+                disk_bytes += file.size as i64 - current as i64;
+            }
+        }
+    }
+
+    let mut from_store = 0;
+    let mut from_store_files = 0;
+    let mut bundle_sizes = vec![];
+
+    for (bundle, needed) in bundles.values() {
+        let in_store = options.chunk_store.as_ref()
+            .is_some_and(|store| needed.iter().all(|hash| store.contains(hash)));
+
+        if in_store {
+            from_store += 1;
+        } else {
+            bundle_sizes.push(bundle_size(bundle));
+        }
+    }
+
+    let single_files = single_files.values()
+        .filter(|file| {
+            let in_store = options.chunk_store.as_ref()
+                .is_some_and(|store| crate::get_file_chunks(file).iter().all(|(hash, _)| store.contains(hash)));
+
+            if in_store {
+                from_store_files += 1;
+            }
+            !in_store
+        })
+        .collect::<Vec<_>>();
+
+    let bundle_bytes = bundle_sizes.iter().sum::<u64>();
+    let file_bytes = single_files.iter().map(|file| file.size).sum::<u64>();
+
+    // each job keeps its bundle on the disk until it is extracted
+    bundle_sizes.sort_unstable_by(|a, b| b.cmp(a));
+    let temporary_bytes = bundle_sizes.iter().take(options.jobs).sum::<u64>();
+
+    println!("Download: {} bundles ({} bytes) and {} files ({} bytes), {} bytes in total",
+             bundle_sizes.len(), bundle_bytes, single_files.len(), file_bytes, bundle_bytes + file_bytes);
+    if options.chunk_store.is_some() {
+        println!("Chunk store: {} bundles and {} files extracted without downloading them", from_store, from_store_files);
+    }
+    println!("Write: {} bytes", write_bytes);
+    println!("Disk space needed: {} bytes, and up to {} bytes for the bundles being extracted",
+             disk_bytes.max(0), temporary_bytes);
+}
+
+fn print_fragment(fragment: &FragmentInstall) {
+    if fragment.files.is_empty() {
+        println!("  Fragment {}: {} files, up to date", fragment.name, fragment.up_to_date);
+        return;
+    }
+
+    println!("  Fragment {}: {} files, {} up to date, {} to update ({} bytes)",
+             fragment.name, fragment.up_to_date + fragment.files.len(), fragment.up_to_date,
+             fragment.files.len(), fragment.files.iter().map(|file| file.size).sum::<u64>());
+
+    for bundle in &fragment.bundles {
+        let needed = needed_chunks(bundle, &fragment.bundle_files).len();
+        println!("    bundle {} ({} bytes, {} of {} chunks needed)", bundle.hash, bundle_size(bundle), needed, bundle.chunks.len());
+    }
+
+    for file in &fragment.single_files {
+        println!("    file {} ({} bytes)", file.name, file.size);
+    }
+}
+
+/// Chunks of the bundle holding a part of the files.
+fn needed_chunks<'a>(bundle: &'a Bundle, files: &Vec<FileM>) -> Vec<&'a str> {
+    bundle.chunks.iter()
+        .filter(|chunk| !crate::get_files_chunks_concerned(&chunk.hash, files).is_empty())
+        .map(|chunk| chunk.hash.as_str())
+        .collect()
+}
+
+pub fn bundle_size(bundle: &Bundle) -> u64 {
+    bundle.chunks.iter().map(|chunk| chunk.offset + chunk.size).max().unwrap_or(0)
+}
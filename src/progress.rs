@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tracing::info;
+use crate::events::{Event, Observer};
+use crate::Install;
+
+/// Progress bars of a download: one per fragment to update and one for the
+/// whole download, in bytes written, with the throughput and the ETA.
+/// The bars are only drawn when asked, i.e. by the command line.
+/// Also sends the events of the download to its observer, if any.
+pub struct Progress {
+    bars: MultiProgress,
+    total: ProgressBar,
+    fragments: Vec<(PathBuf, ProgressBar)>,
+    observer: Option<Arc<dyn Observer>>,
+    /// Bytes left to write in each file, and its event once they are.
+    files: Mutex<HashMap<PathBuf, (u64, Event)>>,
+}
+
+impl Progress {
+    pub fn new(installs: &[Install], observer: Option<Arc<dyn Observer>>, draw: bool) -> Progress {
+        let bars = match draw {
+            true => crate::logging::bars().clone(),
+            false => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        };
+        let several = installs.len() > 1;
+
+        let fragment_style = ProgressStyle::with_template("{prefix:>24} [{bar:30}] {bytes}/{total_bytes}")
+            .unwrap()
+            .progress_chars("=> ");
+
+        let mut fragments = vec![];
+        let mut total_bytes = 0;
+
+        for install in installs {
+            for fragment in install.fragments.iter().filter(|fragment| !fragment.files.is_empty()) {
+                let bytes = fragment.files.iter().map(|file| file.size).sum::<u64>();
+                total_bytes += bytes;
+
+                let prefix = match several {
+                    true => format!("{}/{}/{}", install.target.game, install.target.platform, fragment.name),
+                    false => fragment.name.clone(),
+                };
+
+                let bar = bars.add(ProgressBar::new(bytes).with_style(fragment_style.clone()).with_prefix(prefix));
+                fragments.push((fragment.path.clone(), bar));
+            }
+        }
+
+        let mut files = HashMap::new();
+
+        if observer.is_some() {
+            for install in installs {
+                for fragment in &install.fragments {
+                    for file in &fragment.files {
+                        let event = Event::FileCompleted {
+                            game: install.target.game.clone(),
+                            version: install.target.version.clone(),
+                            fragment: fragment.name.clone(),
+                            path: file.name.clone(),
+                            size: file.size,
+                            hash: file.hash.clone(),
+                        };
+
+                        match file.size {
+                            // nothing is written to the empty files
+                            0 => observer.iter().for_each(|observer| observer.on_event(&event)),
+                            size => { files.insert(fragment.path.join(&file.name), (size, event)); },
+                        }
+                    }
+                }
+            }
+        }
+
+        let total_style = ProgressStyle::with_template("{prefix:>24} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec}, ETA {eta}")
+            .unwrap()
+            .progress_chars("=> ");
+
+        let total = bars.add(ProgressBar::new(total_bytes).with_style(total_style).with_prefix("total"));
+        if draw {
+            total.enable_steady_tick(Duration::from_millis(200));
+        }
+
+        Progress { bars, total, fragments, observer, files: Mutex::new(files) }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Counts `bytes` written to the installed file at `path`.
+    pub fn written(&self, path: &Path, bytes: u64) {
+        if let Some((_, bar)) = self.fragments.iter().find(|(fragment, _)| path.starts_with(fragment)) {
+            bar.inc(bytes);
+        }
+        self.total.inc(bytes);
+
+        if self.observer.is_none() {
+            return;
+        }
+
+        let mut files = self.files.lock().unwrap_or_else(|err| err.into_inner());
+        let completed = match files.get_mut(path) {
+            Some((left, _)) => {
+                *left = left.saturating_sub(bytes);
+                *left == 0
+            },
+            None => false,
+        };
+
+        if completed {
+            if let Some((_, event)) = files.remove(path) {
+                self.emit(event);
+            }
+        }
+    }
+
+    /// Replaces the bars with a summary, so they do not pile up across the
+    /// downloads of a `watch`.
+    pub fn finish(&self) {
+        for (_, bar) in &self.fragments {
+            self.bars.remove(bar);
+        }
+        self.bars.remove(&self.total);
+
+        let (bytes, elapsed) = (self.total.position(), self.total.elapsed());
+        let rate = (bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+        info!("{} written in {} ({}/s)", HumanBytes(bytes), HumanDuration(elapsed), HumanBytes(rate));
+    }
+}
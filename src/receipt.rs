@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::state::STATE_DIR;
+use tracing::error;
+
+const RECEIPT_FILE: &str = "receipt.json";
+
+/// What was installed in an install root, written once a download completes.
+#[derive(Serialize, Deserialize)]
+pub struct Receipt {
+    pub game: String,
+    pub release: String,
+    pub platform: String,
+    pub version: String,
+    pub manifest_hash: String,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// SHA-1 of every installed file, keyed by `fragment/path`.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Receipt {
+    pub fn new(game: &str, release: &str, platform: &str, version: &str, manifest_hash: &str) -> Receipt {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        Receipt {
+            game: game.to_string(),
+            release: release.to_string(),
+            platform: platform.to_string(),
+            version: version.to_string(),
+            manifest_hash: manifest_hash.to_string(),
+            timestamp,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// The receipt of the install root, or `None` if nothing was installed there.
+    pub fn load(root: &Path) -> Result<Option<Receipt>, ()> {
+        let path = receipt_path(root);
+
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(_) => return Ok(None),
+        };
+
+        serde_json::from_slice(&content).map(Some).map_err(|err| {
+            error!("could not parse the receipt {path}: {err}", path = path.display());
+        })
+    }
+
+    /// Receipts of every `game/platform[-release]` install root under `out`.
+    pub fn find_all(out: &Path) -> Result<Vec<Receipt>, ()> {
+        let mut receipts = vec![];
+
+        for game in read_dirs(out) {
+            for platform in read_dirs(&game) {
+                if let Some(receipt) = Receipt::load(&platform)? {
+                    receipts.push(receipt);
+                }
+            }
+        }
+
+        Ok(receipts)
+    }
+
+    pub fn save(&self, root: &Path) -> Result<(), ()> {
+        crate::create_dir_all(&root.join(STATE_DIR))?;
+
+        let content = serde_json::to_vec_pretty(self).map_err(|err| {
+            error!("could not serialize the receipt: {err}");
+        })?;
+
+        crate::write_atomic(&receipt_path(root), &content)
+    }
+}
+
+fn receipt_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(RECEIPT_FILE)
+}
+
+fn read_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut dirs = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => vec![],
+    };
+
+    dirs.sort();
+    dirs
+}
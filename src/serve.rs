@@ -0,0 +1,282 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use hyper::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::io::SeekFrom;
+use std::pin::Pin;
+use futures_util::{future, stream, Stream, StreamExt};
+use hyper::body::Bytes;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use crate::args::Args;
+use crate::DEFAULT_DIR_MIRROR;
+use tracing::{error, info};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+const BOUNDARY: &str = "CYTRUS_BYTERANGES";
+
+type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+pub async fn serve_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let root = PathBuf::from(args.positional(0).unwrap_or(DEFAULT_DIR_MIRROR));
+
+    let listen = args.value("listen").unwrap_or(DEFAULT_LISTEN);
+    let addr: SocketAddr = listen.parse().map_err(|err| {
+        error!("invalid address {listen}: {err}");
+    })?;
+
+    if !root.is_dir() {
+        error!("{path} is not a directory", path = root.display());
+        return Err(());
+    }
+
+    serve(root, addr).await
+}
+
+/// Serves `root` with the cytrus url scheme (`/cytrus.json`,
+/// `/{game}/releases/...`, `/{game}/bundles/...`, `/{game}/hashes/...`).
+async fn serve(root: PathBuf, addr: SocketAddr) -> Result<(), ()> {
+    let root = Arc::new(root);
+
+    let make_service = make_service_fn(move |_| {
+        let root = root.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let root = root.clone();
+                async move { Ok::<_, Infallible>(handle(&root, req).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr).map_err(|err| {
+        error!("could not listen on {addr}: {err}");
+    })?;
+
+    info!("Serving on http://{addr}");
+
+    server.serve(make_service).await.map_err(|err| {
+        error!("the server stopped: {err}");
+    })
+}
+
+async fn handle(root: &Path, req: Request<Body>) -> Response<Body> {
+    let response = match *req.method() {
+        Method::GET | Method::HEAD => respond(root, &req).await,
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    info!("{} {} {}", req.method(), req.uri().path(), response.status().as_u16());
+
+    response
+}
+
+/// Answers from the metadata of the file, its content is only read while the
+/// body is streamed, and never for a `HEAD`.
+async fn respond(root: &Path, req: &Request<Body>) -> Response<Body> {
+    let path = match resolve(root, req.uri().path()) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+
+    let len = match tokio::fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => return status(StatusCode::NOT_FOUND),
+    };
+
+    let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    };
+
+    let range = req.headers().get(RANGE).and_then(|range| range.to_str().ok());
+
+    let ranges = match range.map(|range| parse_ranges(range, len)) {
+        None => None,
+        Some(Some(ranges)) => Some(ranges),
+        Some(None) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    let head = req.method() == Method::HEAD;
+    let builder = Response::builder().header(ACCEPT_RANGES, "bytes");
+
+    let ranges = match ranges {
+        None => {
+            let body = match head {
+                true => Body::empty(),
+                false => match stream_range(&path, 0, len).await {
+                    Ok(body) => Body::wrap_stream(body),
+                    Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+                },
+            };
+
+            return builder
+                .header(CONTENT_TYPE, content_type)
+                .header(CONTENT_LENGTH, len)
+                .body(body)
+                .unwrap();
+        },
+        Some(ranges) => ranges,
+    };
+
+    let builder = builder.status(StatusCode::PARTIAL_CONTENT);
+
+    if let [(start, end)] = ranges[..] {
+        let body = match head {
+            true => Body::empty(),
+            false => match stream_range(&path, start, end - start + 1).await {
+                Ok(body) => Body::wrap_stream(body),
+                Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+            },
+        };
+
+        return builder
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, end - start + 1)
+            .header(CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+            .body(body)
+            .unwrap();
+    }
+
+    // each part is streamed after its headers, the length is known beforehand
+    let mut parts: Vec<ByteStream> = vec![];
+    let mut content_length = 0;
+
+    for (start, end) in ranges {
+        let headers = format!("\r\n--{BOUNDARY}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {start}-{end}/{len}\r\n\r\n");
+        content_length += headers.len() as u64 + end - start + 1;
+
+        if !head {
+            parts.push(Box::pin(stream::once(future::ready(Ok(Bytes::from(headers))))));
+            match stream_range(&path, start, end - start + 1).await {
+                Ok(part) => parts.push(part),
+                Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+    }
+
+    let end = format!("\r\n--{BOUNDARY}--\r\n");
+    content_length += end.len() as u64;
+
+    let body = match head {
+        true => Body::empty(),
+        false => {
+            parts.push(Box::pin(stream::once(future::ready(Ok(Bytes::from(end))))));
+            Body::wrap_stream(stream::iter(parts).flatten())
+        }
+    };
+
+    builder
+        .header(CONTENT_TYPE, format!("multipart/byteranges; boundary={BOUNDARY}"))
+        .header(CONTENT_LENGTH, content_length)
+        .body(body)
+        .unwrap()
+}
+
+/// Maps the url path to a file under `root`, refusing anything escaping it.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        if segment.starts_with('.') || segment.contains('\\') || segment.contains(':') {
+            return None;
+        }
+        resolved.push(segment);
+    }
+
+    if resolved == root {
+        return None;
+    }
+
+    Some(resolved)
+}
+
+/// Parses a `bytes=` range header into inclusive `(start, end)` pairs,
+/// `None` if no range can be satisfied.
+fn parse_ranges(header: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+    let mut ranges = vec![];
+
+    for range in header.strip_prefix("bytes=")?.split(',') {
+        let (start, end) = range.trim().split_once('-')?;
+
+        let (start, end) = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let suffix = suffix.parse::<u64>().ok()?.min(len);
+                (len - suffix, len.checked_sub(1)?)
+            },
+            (start, "") => (start.parse::<u64>().ok()?, len.checked_sub(1)?),
+            (start, end) => (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?.min(len.checked_sub(1)?)),
+        };
+
+        if start <= end && start < len {
+            ranges.push((start, end));
+        }
+    }
+
+    if ranges.is_empty() {
+        return None;
+    }
+
+    Some(ranges)
+}
+
+/// `size` bytes of the file from `offset`, read as the stream is polled.
+async fn stream_range(path: &Path, offset: u64, size: u64) -> std::io::Result<ByteStream> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    Ok(Box::pin(ReaderStream::new(file.take(size))))
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(status.canonical_reason().unwrap_or("")))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_ranges;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_ranges("bytes=0-9", 100), Some(vec![(0, 9)]));
+        assert_eq!(parse_ranges("bytes=90-", 100), Some(vec![(90, 99)]));
+        assert_eq!(parse_ranges("bytes=90-200", 100), Some(vec![(90, 99)]));
+        assert_eq!(parse_ranges("bytes=0-0, 10-19", 100), Some(vec![(0, 0), (10, 19)]));
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_ranges("bytes=-10", 100), Some(vec![(90, 99)]));
+        assert_eq!(parse_ranges("bytes=-200", 100), Some(vec![(0, 99)]));
+        assert_eq!(parse_ranges("bytes=-0", 100), None);
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(parse_ranges("bytes=100-", 100), None);
+        assert_eq!(parse_ranges("bytes=20-10", 100), None);
+        assert_eq!(parse_ranges("bytes=0-9", 0), None);
+        assert_eq!(parse_ranges("bytes=-10", 0), None);
+        assert_eq!(parse_ranges("bytes=-0", 0), None);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert_eq!(parse_ranges("items=0-9", 100), None);
+        assert_eq!(parse_ranges("bytes=a-9", 100), None);
+        assert_eq!(parse_ranges("bytes=10", 100), None);
+    }
+}
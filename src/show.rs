@@ -0,0 +1,128 @@
+use std::fs;
+use glob::Pattern;
+use crate::args::Args;
+use crate::models::Manifest;
+use crate::{DEFAULT_GAME, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::error;
+
+pub async fn manifest_from_args(args: &[String]) -> Result<(), ()> {
+    match args.get(2).map(|action| action.as_str()) {
+        Some("show") => show_from_args(&Args::parse(&args[3..])).await,
+        _ => {
+            error!("unknown manifest action, expected: manifest show");
+            Err(())
+        }
+    }
+}
+
+async fn show_from_args(args: &Args) -> Result<(), ()> {
+    let manifest = match args.value("file") {
+        Some(file) => {
+            let bytes = fs::read(file).map_err(|err| {
+                error!("could not read the file: {file} ({err})");
+            })?;
+            crate::parse_manifest(&bytes)?
+        },
+        None => {
+            let game = args.positional(0).unwrap_or(DEFAULT_GAME);
+            let mut version = args.positional(1).unwrap_or("0").to_string();
+            let platform = args.positional(2).unwrap_or(DEFAULT_PLATFORM);
+            let release = args.positional(3).unwrap_or(DEFAULT_RELEASE);
+
+            if version == "0" {
+                version = crate::get_latest_version(game, platform, release).await?;
+            }
+
+            crate::get_manifest(game, &version, platform, release).await?
+        }
+    };
+
+    let fragments = args.values("fragment");
+    let path = match args.value("path") {
+        Some(path) => Some(Pattern::new(path).map_err(|err| {
+            error!("invalid path pattern {path}: {err}");
+        })?),
+        None => None,
+    };
+
+    let manifest = filter(manifest, &fragments, path.as_ref());
+
+    match args.value("format").unwrap_or("json") {
+        "json" => {
+            let json = serde_json::to_string_pretty(&manifest).map_err(|err| {
+                error!("could not serialize the manifest: {err}");
+            })?;
+            println!("{json}");
+        },
+        "yaml" => {
+            let yaml = serde_yaml::to_string(&manifest).map_err(|err| {
+                error!("could not serialize the manifest: {err}");
+            })?;
+            print!("{yaml}");
+        },
+        "csv" => print_csv(&manifest),
+        format => {
+            error!("unknown format {format}, expected json, yaml or csv");
+            return Err(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the fragments of a version, to pick the ones given to `download --fragment`.
+pub async fn fragments_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+    let game = args.positional(0).unwrap_or(DEFAULT_GAME);
+    let mut version = args.positional(1).unwrap_or("0").to_string();
+    let platform = args.positional(2).unwrap_or(DEFAULT_PLATFORM);
+    let release = args.positional(3).unwrap_or(DEFAULT_RELEASE);
+
+    if version == "0" {
+        version = crate::get_latest_version(game, platform, release).await?;
+    }
+
+    let manifest = crate::get_manifest(game, &version, platform, release).await?;
+
+    println!("{} version {}", game, version);
+
+    for fragment in &manifest.fragments {
+        let size = fragment.files.iter().map(|file| file.size).sum::<u64>();
+        println!("  {}: {} files, {} bytes", fragment.name, fragment.files.len(), size);
+    }
+
+    Ok(())
+}
+
+/// Keeps the fragments named in `fragments` (all if empty) and the files matching `path`,
+/// like `download --include`.
+fn filter(mut manifest: Manifest, fragments: &[&str], path: Option<&Pattern>) -> Manifest {
+    manifest.fragments.retain(|fragment| fragments.is_empty() || fragments.contains(&fragment.name.as_str()));
+
+    if let Some(path) = path {
+        for fragment in &mut manifest.fragments {
+            fragment.files.retain(|file| crate::matches_path(path, &file.name));
+        }
+    }
+
+    manifest
+}
+
+fn print_csv(manifest: &Manifest) {
+    println!("fragment,path,size,hash,executable,symlink,chunk_count");
+
+    for fragment in &manifest.fragments {
+        for file in &fragment.files {
+            println!("{},{},{},{},{},{},{}", csv_field(&fragment.name), csv_field(&file.name), file.size,
+                     file.hash, file.executable, csv_field(&file.symlink), file.chunks.len());
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
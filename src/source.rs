@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use futures_util::StreamExt;
+use crate::CYTRUS_BASE_URL;
+use tracing::{error, warn};
+
+/// Where the cytrus files are read from: the cdn, or any http server or local
+/// directory (e.g. a mirror) with the same layout.
+#[derive(Clone)]
+pub enum Source {
+    Http(String),
+    Local(PathBuf),
+}
+
+static SOURCE: OnceLock<Source> = OnceLock::new();
+
+/// The source given by `--base-url` to the commands, the official cdn by default.
+/// `download` reads from `DownloadOptions::source` instead.
+pub fn source() -> &'static Source {
+    SOURCE.get_or_init(|| Source::Http(CYTRUS_BASE_URL.to_string()))
+}
+
+/// Sets the source of the commands for the rest of the run, must be called before any fetch.
+pub fn set_source(source: Source) {
+    if SOURCE.set(source).is_err() {
+        warn!("the source is already set, ignoring --base-url");
+    }
+}
+
+impl Source {
+    /// `http(s)://...` urls are fetched, `file://...` urls and plain paths are read from the disk.
+    pub fn parse(url: &str) -> Source {
+        let url = url.trim_end_matches('/');
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Source::Http(url.to_string());
+        }
+
+        let path = match url.strip_prefix("file://") {
+            // file:///C:/mirror on windows
+            Some(path) if path.as_bytes().get(2) == Some(&b':') => &path[1..],
+            Some(path) => path,
+            None => url,
+        };
+
+        Source::Local(PathBuf::from(path))
+    }
+
+    /// Location of the file at `path` (e.g. `cytrus.json`), for the messages.
+    pub fn url(&self, path: &str) -> String {
+        match self {
+            Source::Http(base) => format!("{base}/{path}"),
+            Source::Local(root) => root.join(path).display().to_string(),
+        }
+    }
+
+    /// Name of the source in the local caches, so what was read from a mirror
+    /// or a packed directory never passes for what the cdn serves.
+    pub fn cache_key(&self) -> String {
+        let name = match self {
+            Source::Http(base) => base.split_once("://").map_or(base.as_str(), |(_, rest)| rest).to_string(),
+            Source::Local(root) => format!("file/{}", std::fs::canonicalize(root).unwrap_or_else(|_| root.clone()).display()),
+        };
+
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect()
+    }
+
+    pub async fn get(&self, path: &str) -> Result<Vec<u8>, ()> {
+        match self {
+            Source::Http(_) => {
+                let res = self.send(path).await?;
+
+                let bytes = res.bytes().await.map_err(|err| {
+                    error!("could not read the url: {err}");
+                })?;
+
+                Ok(bytes.to_vec())
+            },
+            Source::Local(root) => {
+                let file_path = root.join(path);
+                tokio::fs::read(&file_path).await.map_err(|err| {
+                    error!("could not read the file: {path} ({err})", path = file_path.display());
+                })
+            }
+        }
+    }
+
+    pub async fn exists(&self, path: &str) -> bool {
+        match self {
+            Source::Http(_) => crate::http_client().head(self.url(path)).send()
+                .await
+                .is_ok_and(|res| res.status().is_success()),
+            Source::Local(root) => root.join(path).is_file(),
+        }
+    }
+
+    /// Reads `size` bytes of the file at `path` from `offset`, with a range request over http.
+    pub async fn get_range(&self, path: &str, offset: u64, size: u64) -> Result<Vec<u8>, ()> {
+        if size == 0 {
+            return Ok(vec![]);
+        }
+
+        match self {
+            Source::Http(_) => {
+                let res = crate::http_client().get(self.url(path))
+                    .header(reqwest::header::RANGE, format!("bytes={}-{}", offset, offset + size - 1))
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(|err| {
+                        error!("could not fetch the url: {err}");
+                    })?;
+
+                let partial = res.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+
+                let bytes = res.bytes().await.map_err(|err| {
+                    error!("could not read the url: {err}");
+                })?;
+
+                // a server ignoring the range sends the whole file
+                let bytes = if partial { &bytes[..] } else { bytes.get(offset as usize..).unwrap_or(&[]) };
+
+                bytes.get(..size as usize).map(|bytes| bytes.to_vec()).ok_or_else(|| {
+                    error!("{url} is shorter than expected", url = self.url(path));
+                })
+            },
+            Source::Local(root) => {
+                let file_path = root.join(path);
+                let mut buffer = vec![0; size as usize];
+
+                let mut file = File::open(&file_path).map_err(|err| {
+                    error!("could not open the file: {path} ({err})", path = file_path.display());
+                })?;
+
+                file.seek(SeekFrom::Start(offset)).and_then(|_| file.read_exact(&mut buffer)).map_err(|err| {
+                    error!("could not read the file: {path} ({err})", path = file_path.display());
+                })?;
+
+                Ok(buffer)
+            }
+        }
+    }
+
+    /// Copies the file at `path` to `destination`, streaming it when it comes from the network.
+    /// `progress` is given the number of bytes copied so far.
+    pub async fn download_to(&self, path: &str, destination: &Path, mut progress: impl FnMut(u64)) -> Result<(), ()> {
+        match self {
+            Source::Http(_) => {
+                let res = self.send(path).await?;
+
+                let mut file = File::create(destination).map_err(|err| {
+                    error!("could not create the file: {path} ({err})", path = destination.display());
+                })?;
+
+                let mut stream = res.bytes_stream();
+                let mut copied = 0;
+
+                while let Some(item) = stream.next().await {
+                    let bytes = item.map_err(|err| {
+                        error!("could not read the url: {err}");
+                    })?;
+
+                    file.write_all(&bytes).map_err(|err| {
+                        error!("could not write the file: {path} ({err})", path = destination.display());
+                    })?;
+
+                    copied += bytes.len() as u64;
+                    progress(copied);
+                }
+
+                Ok(())
+            },
+            Source::Local(root) => {
+                let file_path = root.join(path);
+                tokio::fs::copy(&file_path, destination).await.map(progress).map_err(|err| {
+                    error!("could not copy the file: {path} ({err})", path = file_path.display());
+                })
+            }
+        }
+    }
+
+    async fn send(&self, path: &str) -> Result<reqwest::Response, ()> {
+        crate::http_client().get(self.url(path)).send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| {
+                error!("could not fetch the url: {err}");
+            })
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+/// Directory, relative to the install root, holding the downloader's own files.
+pub const STATE_DIR: &str = ".cytrus";
+const STATE_FILE: &str = "state";
+
+/// Persistent index of the installed files, so an unchanged file (same size
+/// and modification time) is not hashed again on every run.
+#[derive(Serialize, Deserialize, Default)]
+pub struct HashState {
+    files: HashMap<String, HashEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HashEntry {
+    size: u64,
+    mtime: u64,
+    sha1: String,
+}
+
+impl HashState {
+    pub fn load(root: &Path) -> HashState {
+        let path = state_path(root);
+
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(_) => return HashState::default(),
+        };
+
+        serde_json::from_slice(&content).unwrap_or_else(|err| {
+            warn!("ignoring the corrupted hash state {path}: {err}", path = path.display());
+            HashState::default()
+        })
+    }
+
+    pub fn save(&self, root: &Path) -> Result<(), ()> {
+        let path = state_path(root);
+        crate::create_dir_all(&root.join(STATE_DIR))?;
+
+        let content = serde_json::to_vec(self).map_err(|err| {
+            error!("could not serialize the hash state: {err}");
+        })?;
+
+        crate::write_atomic(&path, &content)
+    }
+
+    /// SHA-1 of the file `name` (relative to `root`), or `None` if it does not exist.
+    /// The file is only read when its size or mtime changed since it was last
+    /// hashed, or when `rehash` is set.
+    pub fn hash(&mut self, root: &Path, name: &str, rehash: bool) -> Result<Option<String>, ()> {
+        let path = root.join(name);
+
+        let (size, mtime) = match stamp(&path) {
+            Some(stamp) => stamp,
+            None => {
+                self.files.remove(name);
+                return Ok(None);
+            }
+        };
+
+        if !rehash {
+            if let Some(entry) = self.files.get(name) {
+                if entry.size == size && entry.mtime == mtime {
+                    return Ok(Some(entry.sha1.clone()));
+                }
+            }
+        }
+
+        let sha1 = crate::sha1(&path)?;
+        self.files.insert(name.to_string(), HashEntry { size, mtime, sha1: sha1.clone() });
+
+        Ok(Some(sha1))
+    }
+
+    /// Records `sha1` as the hash of the file `name` as it is currently on disk.
+    pub fn record(&mut self, root: &Path, name: &str, sha1: &str) {
+        match stamp(&root.join(name)) {
+            Some((size, mtime)) => {
+                self.files.insert(name.to_string(), HashEntry { size, mtime, sha1: sha1.to_string() });
+            },
+            None => {
+                self.files.remove(name);
+            }
+        }
+    }
+}
+
+fn state_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(STATE_FILE)
+}
+
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let mtime = metadata.modified().ok()?
+        .duration_since(UNIX_EPOCH).ok()?
+        .as_nanos() as u64;
+
+    Some((metadata.len(), mtime))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::HashState;
+
+    /// An empty directory of its own for each test, as they run in parallel.
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cytrus-state-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn hashes_the_files() {
+        let root = test_root("hash");
+        let mut state = HashState::default();
+
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(None));
+
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(Some(crate::sha1_bytes(b"hello"))));
+
+        fs::remove_file(root.join("a.txt")).unwrap();
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(None));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn trusts_the_recorded_hash_of_unchanged_files() {
+        let root = test_root("record");
+        let mut state = HashState::default();
+
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        state.record(&root, "a.txt", "recorded");
+
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(Some("recorded".to_string())));
+        assert_eq!(state.hash(&root, "a.txt", true), Ok(Some(crate::sha1_bytes(b"hello"))));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn hashes_again_the_files_whose_size_changed() {
+        let root = test_root("changed");
+        let mut state = HashState::default();
+
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        state.record(&root, "a.txt", "recorded");
+        fs::write(root.join("a.txt"), "hello world").unwrap();
+
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(Some(crate::sha1_bytes(b"hello world"))));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn saves_and_loads_the_recorded_hashes() {
+        let root = test_root("save");
+        let mut state = HashState::default();
+
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        state.record(&root, "a.txt", "recorded");
+        state.record(&root, "missing.txt", "recorded");
+        state.save(&root).unwrap();
+
+        let mut state = HashState::load(&root);
+        assert_eq!(state.hash(&root, "a.txt", false), Ok(Some("recorded".to_string())));
+        assert!(!state.files.contains_key("missing.txt"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
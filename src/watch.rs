@@ -0,0 +1,226 @@
+use std::path::Path;
+use std::time::Duration;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use crate::args::Args;
+use crate::history;
+use crate::models::CytrusRoot;
+use crate::receipt::Receipt;
+use crate::source::{source, Source};
+use crate::{DownloadOptions, CYTRUS_JSON, DEFAULT_DIR_OUT, DEFAULT_GAME, DEFAULT_PLATFORM, DEFAULT_RELEASE};
+use tracing::{error, info};
+
+const DEFAULT_INTERVAL: u64 = 300;
+
+struct Target {
+    game: String,
+    platform: String,
+    release: String,
+    current: Option<String>,
+}
+
+/// Polls `cytrus.json`, with conditional requests over http, keeping the last body seen.
+struct Poller {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: Option<CytrusRoot>,
+}
+
+impl Poller {
+    fn new() -> Poller {
+        Poller {
+            etag: None,
+            last_modified: None,
+            body: None,
+        }
+    }
+
+    /// Refreshes the body, returns `true` if it changed since the last poll.
+    async fn poll(&mut self) -> Result<bool, ()> {
+        let source = source();
+
+        let body = match source {
+            Source::Http(_) => match self.fetch(&source.url(CYTRUS_JSON)).await? {
+                Some(body) => body,
+                None => return Ok(false),
+            },
+            Source::Local(_) => crate::parse_cytrus_root(&source.get(CYTRUS_JSON).await?)?,
+        };
+
+        if self.body.as_ref() == Some(&body) {
+            return Ok(false);
+        }
+
+        self.body = Some(body);
+        Ok(true)
+    }
+
+    /// The body, or `None` if the server answered it was not modified.
+    async fn fetch(&mut self, url: &str) -> Result<Option<CytrusRoot>, ()> {
+        let mut req = crate::http_client().get(url);
+
+        if let Some(etag) = &self.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = &self.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let res = req.send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| {
+                error!("could not fetch the url: {}", err);
+            })?;
+
+        if res.status() == StatusCode::NOT_MODIFIED && self.body.is_some() {
+            return Ok(None);
+        }
+
+        let header = |name| res.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        let bytes = res.bytes().await.map_err(|err| {
+            error!("could not read the url: {}", err);
+        })?;
+
+        let body = crate::parse_cytrus_root(&bytes)?;
+
+        self.etag = etag;
+        self.last_modified = last_modified;
+
+        Ok(Some(body))
+    }
+}
+
+pub async fn watch_from_args(args: &[String]) -> Result<(), ()> {
+    let args = Args::parse(&args[2..]);
+
+    let interval = match args.value("interval") {
+        Some(interval) => interval.parse::<u64>().map_err(|err| {
+            error!("invalid interval {interval}: {err}");
+        })?,
+        None => DEFAULT_INTERVAL,
+    };
+
+    let mut targets = vec![];
+    let mut index = 0;
+
+    while let Some(target) = args.positional(index) {
+        targets.push(parse_target(target)?);
+        index += 1;
+    }
+
+    if targets.is_empty() {
+        targets.push(parse_target(&format!("{DEFAULT_GAME}/{DEFAULT_PLATFORM}/{DEFAULT_RELEASE}"))?);
+    }
+
+    watch(targets, Duration::from_secs(interval), args.value("hook"), crate::cli::cancel_on_ctrl_c()).await
+}
+
+fn parse_target(target: &str) -> Result<Target, ()> {
+    let (game, platform, release) = crate::parse_target(target)?;
+
+    let receipt = Receipt::load(&crate::install_root(Path::new(DEFAULT_DIR_OUT), &game, &platform, &release))?;
+    let current = receipt
+        .filter(|receipt| receipt.release == release)
+        .map(|receipt| receipt.version);
+
+    Ok(Target { game, platform, release, current })
+}
+
+async fn watch(mut targets: Vec<Target>, interval: Duration, hook: Option<&str>, cancel: CancellationToken) -> Result<(), ()> {
+    let mut poller = Poller::new();
+
+    loop {
+        match poller.poll().await {
+            Ok(changed) => {
+                if changed {
+                    info!("cytrus.json changed");
+                }
+                if let Some(body) = &poller.body {
+                    history::record(body);
+                }
+            },
+            Err(_) => error!("could not poll cytrus, retrying in {}s", interval.as_secs()),
+        }
+
+        // targets are checked on every poll, so a failed update is retried
+        if let Some(body) = &poller.body {
+            for target in targets.iter_mut() {
+                update_target(body, target, hook, &cancel).await;
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {},
+            _ = cancel.cancelled() => return Ok(()),
+        }
+    }
+}
+
+async fn update_target(body: &CytrusRoot, target: &mut Target, hook: Option<&str>, cancel: &CancellationToken) {
+    let latest = match crate::find_version(body, &target.game, &target.platform, &target.release) {
+        Ok(latest) => latest,
+        Err(_) => return,
+    };
+
+    if target.current.as_deref() == Some(latest.as_str()) {
+        return;
+    }
+
+    info!("{}/{}/{}: {} -> {}", target.game, target.platform, target.release,
+             target.current.as_deref().unwrap_or("not installed"), latest);
+
+    let options = DownloadOptions {
+        cancel: cancel.clone(),
+        source: source().clone(),
+        progress_bars: true,
+        ..Default::default()
+    };
+
+    if crate::download(&target.game, &latest, &target.platform, &target.release, &options).await.is_err() {
+        if cancel.is_cancelled() {
+            return;
+        }
+        error!("could not update {}/{}/{} to {}", target.game, target.platform, target.release, latest);
+        return;
+    }
+
+    if let Some(hook) = hook {
+        run_hook(hook, target, &latest).await;
+    }
+
+    target.current = Some(latest);
+}
+
+async fn run_hook(hook: &str, target: &Target, new_version: &str) {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(hook);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(hook);
+        command
+    };
+
+    let status = command
+        .env("CYTRUS_GAME", &target.game)
+        .env("CYTRUS_PLATFORM", &target.platform)
+        .env("CYTRUS_RELEASE", &target.release)
+        .env("CYTRUS_OLD_VERSION", target.current.as_deref().unwrap_or(""))
+        .env("CYTRUS_NEW_VERSION", new_version)
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => {},
+        Ok(status) => error!("the hook exited with {status}"),
+        Err(err) => error!("could not run the hook: {err}"),
+    }
+}